@@ -0,0 +1,43 @@
+//! Owned, borrowed and iterated deserialization of large responses.
+
+mod common;
+
+use common::Replay;
+use criterion::{criterion_group, criterion_main, Criterion};
+use yggdrasilctl::{Endpoint, RouterVersion};
+
+fn endpoint(name: &str, response: serde_json::Value) -> Endpoint<Replay> {
+    let socket = Replay::new(common::response(name, response, true));
+    Endpoint::attach_version(socket, RouterVersion::v0_5_0__)
+}
+
+fn tree(c: &mut Criterion) {
+    let mut e = endpoint("gettree", common::tree(10_000));
+    let mut group = c.benchmark_group("gettree");
+    group.bench_function("owned", |b| b.iter(|| e.get_tree().unwrap().unwrap().len()));
+    group.bench_function("borrowed", |b| {
+        b.iter(|| e.get_tree_ref().unwrap().unwrap().len())
+    });
+    group.bench_function("iterated", |b| {
+        b.iter(|| e.iter_tree().unwrap().unwrap().map(Result::unwrap).count())
+    });
+    group.finish();
+}
+
+fn paths(c: &mut Criterion) {
+    let mut e = endpoint("getpaths", common::paths(10_000));
+    let mut group = c.benchmark_group("getpaths");
+    group.bench_function("owned", |b| {
+        b.iter(|| e.get_paths().unwrap().unwrap().len())
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| e.get_paths_ref().unwrap().unwrap().len())
+    });
+    group.bench_function("iterated", |b| {
+        b.iter(|| e.iter_paths().unwrap().unwrap().map(Result::unwrap).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tree, paths);
+criterion_main!(benches);
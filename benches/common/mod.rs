@@ -0,0 +1,155 @@
+//! Helpers shared by benchmarks.
+
+// Every benchmark uses only some of the helpers
+#![allow(dead_code)]
+
+use serde_json::Value;
+use std::{
+    io::{self, Read, Write},
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+/// Socket answering every request with the same response, at most `chunk` bytes per read.
+pub struct Replay {
+    response: Vec<u8>,
+    pos: usize,
+    chunk: usize,
+}
+
+impl Replay {
+    pub fn new(response: Vec<u8>) -> Self {
+        Self::chunked(response, usize::MAX)
+    }
+
+    pub fn chunked(response: Vec<u8>, chunk: usize) -> Self {
+        Self {
+            response,
+            pos: 0,
+            chunk,
+        }
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.response.len() {
+            self.pos = 0;
+        }
+        let end = self.response.len().min(self.pos.saturating_add(self.chunk));
+        let read = (&self.response[self.pos..end]).read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// One end of an in-process pipe, see `pipe`.
+pub struct Duplex {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+/// Connected pair of sockets, either end reads EOF once the other is dropped.
+pub fn pipe() -> (Duplex, Duplex) {
+    let end = |tx, rx| Duplex {
+        tx,
+        rx,
+        chunk: Vec::new(),
+        pos: 0,
+    };
+    let (a_tx, a_rx) = channel();
+    let (b_tx, b_rx) = channel();
+    (end(a_tx, b_rx), end(b_tx, a_rx))
+}
+
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => (self.chunk, self.pos) = (chunk, 0),
+                Err(_) => return Ok(0),
+            }
+        }
+        let read = (&self.chunk[self.pos..]).read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Empty chunks would read as EOF
+        if !buf.is_empty() {
+            self.tx
+                .send(buf.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Successful response to `request`, formatted like the router does when `pretty` is set.
+pub fn response(request: &str, response: Value, pretty: bool) -> Vec<u8> {
+    let json = serde_json::json!({
+        "status": "success",
+        "request": { "request": request },
+        "response": response,
+    });
+    let mut buf = match pretty {
+        true => serde_json::to_vec_pretty(&json).unwrap(),
+        false => serde_json::to_vec(&json).unwrap(),
+    };
+    buf.push(b'\n');
+    buf
+}
+
+pub fn key(i: usize) -> String {
+    format!("{i:064x}")
+}
+
+pub fn address(i: usize) -> String {
+    format!("200::{:x}:{:x}", i >> 16, i & 0xffff)
+}
+
+/// `gettree` entries of routers since v0.5.0.
+pub fn tree(entries: usize) -> Value {
+    let tree: Vec<_> = (0..entries)
+        .map(|i| {
+            serde_json::json!({
+                "address": address(i),
+                "key": key(i),
+                "parent": key(i / 2),
+                "sequence": i,
+            })
+        })
+        .collect();
+    serde_json::json!({ "tree": tree })
+}
+
+/// `getpaths` entries of routers since v0.4.5.
+pub fn paths(entries: usize) -> Value {
+    let paths: Vec<_> = (0..entries)
+        .map(|i| {
+            serde_json::json!({
+                "address": address(i),
+                "key": key(i),
+                "path": [1, 2, i % 7 + 1],
+                "sequence": i,
+            })
+        })
+        .collect();
+    serde_json::json!({ "paths": paths })
+}
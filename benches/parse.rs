@@ -0,0 +1,192 @@
+//! Reading responses off the socket and deserializing them for every router version.
+
+mod common;
+
+use common::{address, key, Replay};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Map, Value};
+use yggdrasilctl::{blocking::read_response, Endpoint, EndpointOptions, RouterVersion};
+
+const SIZES: [usize; 3] = [10, 1_000, 100_000];
+
+const VERSIONS: [RouterVersion; 4] = [
+    RouterVersion::v0_3,
+    RouterVersion::__v0_4_4,
+    RouterVersion::v0_4_5__v0_4_7,
+    RouterVersion::v0_5_0__,
+];
+
+fn read(c: &mut Criterion) {
+    let options = EndpointOptions::default();
+    let mut scratch = Vec::new();
+    for pretty in [true, false] {
+        let response = common::response("gettree", common::tree(1_000), pretty);
+        let name = match pretty {
+            true => "read_response/pretty",
+            false => "read_response/compact",
+        };
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(response.len() as u64));
+        for chunk in [64, 1024, 16 * 1024, usize::MAX] {
+            let mut socket = Replay::chunked(response.clone(), chunk);
+            let id = match chunk {
+                usize::MAX => BenchmarkId::from_parameter("whole"),
+                _ => BenchmarkId::from_parameter(chunk),
+            };
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    read_response(&mut socket, &mut scratch, &options)
+                        .unwrap()
+                        .len()
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+// Responses as sent by each router version, keyed the way its wrapper expects
+fn peers(version: &RouterVersion, entries: usize) -> Value {
+    let entry = |i: usize| {
+        json!({
+            "address": address(i),
+            "key": key(i),
+            "port": i + 1,
+            "priority": 0,
+            "coords": [1, 2, i % 7 + 1],
+            "remote": format!("tls://198.51.100.{}:443", i % 250),
+            "bytes_recvd": i * 1000,
+            "bytes_sent": i * 2000,
+            "uptime": i as f64 + 0.5,
+        })
+    };
+    match version {
+        RouterVersion::v0_3 => {
+            let peers: Map<_, _> = (0..entries)
+                .map(|i| {
+                    let entry = json!({
+                        "box_pub_key": key(i),
+                        "bytes_recvd": i * 1000,
+                        "bytes_sent": i * 2000,
+                        "coords": format!("[1 2 {}]", i % 7 + 1),
+                        "endpoint": format!("198.51.100.{}:443", i % 250),
+                        "ip": address(i),
+                        "port": i + 1,
+                        "proto": "tcp",
+                        "uptime": i as f64 + 0.5,
+                    });
+                    ((i + 1).to_string(), entry)
+                })
+                .collect();
+            json!({ "switchpeers": peers })
+        }
+        RouterVersion::__v0_4_4 => {
+            let peers: Map<_, _> = (0..entries).map(|i| (address(i), entry(i))).collect();
+            json!({ "peers": peers })
+        }
+        RouterVersion::v0_4_5__v0_4_7 => {
+            let peers: Vec<_> = (0..entries).map(entry).collect();
+            json!({ "peers": peers })
+        }
+        RouterVersion::v0_5_0__ => {
+            let peers: Vec<_> = (0..entries)
+                .map(|i| {
+                    let mut entry = entry(i);
+                    let map = entry.as_object_mut().unwrap();
+                    map.remove("coords");
+                    map.extend([
+                        ("up".to_string(), json!(true)),
+                        ("inbound".to_string(), json!(i % 2 == 0)),
+                        ("latency".to_string(), json!(i * 1000)),
+                        ("cost".to_string(), json!(1)),
+                    ]);
+                    entry
+                })
+                .collect();
+            json!({ "peers": peers })
+        }
+    }
+}
+
+fn paths(version: &RouterVersion, entries: usize) -> Value {
+    match version {
+        // Derived from sessions, see `Endpoint::get_paths`
+        RouterVersion::v0_3 => {
+            let sessions: Map<_, _> = (0..entries)
+                .map(|i| {
+                    let entry = json!({
+                        "box_pub_key": key(i),
+                        "bytes_recvd": i * 1000,
+                        "bytes_sent": i * 2000,
+                        "coords": format!("[1 2 {}]", i % 7 + 1),
+                        "mtu": 65535,
+                        "uptime": i as f64 + 0.5,
+                        "was_mtu_fixed": false,
+                    });
+                    (address(i), entry)
+                })
+                .collect();
+            json!({ "sessions": sessions })
+        }
+        RouterVersion::__v0_4_4 => {
+            let paths: Map<_, _> = (0..entries)
+                .map(|i| {
+                    (
+                        address(i),
+                        json!({ "key": key(i), "path": [1, 2, i % 7 + 1] }),
+                    )
+                })
+                .collect();
+            json!({ "paths": paths })
+        }
+        RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ => common::paths(entries),
+    }
+}
+
+fn deserialize(
+    c: &mut Criterion,
+    request: &str,
+    versions: &[RouterVersion],
+    payload: impl Fn(&RouterVersion, usize) -> Value,
+    call: impl Fn(&mut Endpoint<Replay>) -> usize,
+) {
+    for version in versions {
+        let mut group = c.benchmark_group(format!("{request}/{version:?}"));
+        for entries in SIZES {
+            if entries >= 100_000 {
+                group.sample_size(10);
+            }
+            let response = common::response(request, payload(version, entries), true);
+            let mut e = Endpoint::attach_version(Replay::new(response), version.clone());
+            group.throughput(Throughput::Elements(entries as u64));
+            group.bench_function(BenchmarkId::from_parameter(entries), |b| {
+                b.iter(|| call(&mut e))
+            });
+        }
+        group.finish();
+    }
+}
+
+fn get_peers(c: &mut Criterion) {
+    deserialize(c, "getpeers", &VERSIONS, peers, |e| {
+        e.get_peers().unwrap().unwrap().len()
+    });
+}
+
+fn get_paths(c: &mut Criterion) {
+    // Routers since v0.4.5 share the same response format
+    deserialize(c, "getpaths", &VERSIONS[..3], paths, |e| {
+        e.get_paths().unwrap().unwrap().len()
+    });
+}
+
+fn get_tree(c: &mut Criterion) {
+    // Only routers since v0.5.0 report the tree
+    let tree = |_: &RouterVersion, entries| common::tree(entries);
+    deserialize(c, "gettree", &VERSIONS[3..], tree, |e| {
+        e.get_tree().unwrap().unwrap().len()
+    });
+}
+
+criterion_group!(benches, read, get_peers, get_paths, get_tree);
+criterion_main!(benches);
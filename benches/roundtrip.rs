@@ -0,0 +1,73 @@
+//! Requests answered by `Server` over an in-process pipe.
+
+mod common;
+
+use common::Duplex;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::Value;
+use std::collections::HashMap;
+use yggdrasilctl::{
+    server::{Handler, Server},
+    Endpoint, RouterVersion,
+};
+
+/// Answers `name` with the same response every time.
+struct Fixed {
+    name: &'static str,
+    response: Value,
+}
+
+impl Handler for Fixed {
+    fn name(&self) -> &str {
+        self.name
+    }
+    fn handle(&self, _: &HashMap<String, Value>) -> Result<Value, String> {
+        Ok(self.response.clone())
+    }
+}
+
+fn endpoint(handlers: impl IntoIterator<Item = Fixed>) -> Endpoint<Duplex> {
+    let mut server = Server::new();
+    for handler in handlers {
+        server.register(handler);
+    }
+    let (client, socket) = common::pipe();
+    // Exits once the endpoint is dropped
+    std::thread::spawn(move || server.serve_connection(socket));
+    Endpoint::attach_version(client, RouterVersion::v0_5_0__)
+}
+
+fn get_self(c: &mut Criterion) {
+    let mut e = endpoint([Fixed {
+        name: "getself",
+        response: serde_json::json!({
+            "build_name": "yggdrasil",
+            "build_version": "0.5.12",
+            "key": common::key(0),
+            "address": common::address(0),
+            "subnet": "300::/64",
+            "routing_entries": 3,
+        }),
+    }]);
+    c.bench_function("roundtrip/getself", |b| {
+        b.iter(|| e.get_self().unwrap().unwrap())
+    });
+}
+
+fn get_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("roundtrip/gettree");
+    for entries in [10, 1_000] {
+        let mut e = endpoint([Fixed {
+            name: "gettree",
+            response: common::tree(entries),
+        }]);
+        group.throughput(Throughput::Elements(entries as u64));
+        group.bench_function(BenchmarkId::from_parameter(entries), |b| {
+            b.iter(|| e.get_tree().unwrap().unwrap().len())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_self, get_tree);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+//! Helpers shared by fuzz targets.
+
+use std::io::{self, Read, Write};
+
+/// Socket yielding `data` at most `chunk` bytes per read, then EOF.
+/// Anything written to it is discarded.
+pub struct Chunked<'a> {
+    data: &'a [u8],
+    chunk: usize,
+}
+
+impl<'a> Chunked<'a> {
+    /// Take the chunk size from the first byte of `input`, the rest is the data.
+    pub fn split(input: &'a [u8]) -> Option<Self> {
+        let (&chunk, data) = input.split_first()?;
+        Some(Self {
+            data,
+            chunk: chunk as usize + 1,
+        })
+    }
+}
+
+impl Read for Chunked<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+impl Write for Chunked<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
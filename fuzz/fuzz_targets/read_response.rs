@@ -0,0 +1,42 @@
+//! Arbitrary byte streams, split into arbitrary chunks, fed to `read_response`.
+//!
+//! Input: limit selector, initial buffer size, chunk size, then the stream.
+
+#![no_main]
+
+mod common;
+
+use common::Chunked;
+use libfuzzer_sys::fuzz_target;
+use yggdrasilctl::{blocking::read_response, EndpointOptions};
+
+fuzz_target!(|input: &[u8]| {
+    let [limit, initial, rest @ ..] = input else {
+        return;
+    };
+    let Some(mut socket) = Chunked::split(rest) else {
+        return;
+    };
+    let options = EndpointOptions {
+        initial_buffer_size: *initial as usize,
+        max_response_size: match limit {
+            0 => None,
+            limit => Some(*limit as usize * 16),
+        },
+        ..Default::default()
+    };
+    let stream = &rest[1..];
+    let mut scratch = Vec::new();
+    let result = read_response(&mut socket, &mut scratch, &options).map(<[u8]>::len);
+
+    // Never read past the end of the first response, nor grow the buffer past the limit
+    if let Ok(len) = result {
+        assert!(len <= stream.len());
+        assert!(options.max_response_size.is_none_or(|max| len <= max));
+    }
+    if let Some(max) = options.max_response_size {
+        assert!(scratch.len() <= (max + 1).max(options.initial_buffer_size));
+    }
+    // Buffer grows by doubling at most
+    assert!(scratch.len() <= (2 * stream.len()).max(options.initial_buffer_size).max(1));
+});
@@ -0,0 +1,74 @@
+//! Arbitrary responses, split into arbitrary chunks, fed to every `Endpoint` wrapper,
+//! to `request_raw` and to version detection of `attach`.
+//!
+//! Input: router version, wrapper, chunk size, then the response.
+
+#![no_main]
+
+mod common;
+
+use common::Chunked;
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use yggdrasilctl::{Endpoint, EndpointOptions, RouterVersion};
+
+const VERSIONS: [RouterVersion; 4] = [
+    RouterVersion::v0_3,
+    RouterVersion::__v0_4_4,
+    RouterVersion::v0_4_5__v0_4_7,
+    RouterVersion::v0_5_0__,
+];
+
+const KEY: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fuzz_target!(|input: &[u8]| {
+    let [version, wrapper, rest @ ..] = input else {
+        return;
+    };
+    let Some(socket) = Chunked::split(rest) else {
+        return;
+    };
+    let version = VERSIONS[*version as usize % VERSIONS.len()].clone();
+    let options = EndpointOptions {
+        max_response_size: Some(1 << 20),
+        ..Default::default()
+    };
+
+    // Only panics matter, errors are expected for most inputs
+    let wrapper = wrapper % 24;
+    if wrapper == 23 {
+        // Version is detected from the first response, the rest goes to the detected version
+        let mut e = Endpoint::attach_with_options(socket, options);
+        drop(e.get_self());
+        return;
+    }
+    let mut e = Endpoint::attach_version_with_options(socket, version, options);
+    match wrapper {
+        0 => drop(e.get_peers()),
+        1 => drop(e.stream_peers(drop)),
+        2 => drop(e.get_sessions()),
+        3 => drop(e.add_peer("tcp://[::1]:1".into(), None)),
+        4 => drop(e.remove_peer("tcp://[::1]:1".into(), Some("eth0".into()))),
+        5 => drop(e.get_allowed_public_keys()),
+        6 => drop(e.add_allowed_public_key(KEY.into())),
+        7 => drop(e.remove_allowed_public_key(KEY.into())),
+        8 => drop(e.get_self()),
+        9 => drop(e.get_paths()),
+        10 => drop(e.stream_paths(drop)),
+        11 => drop(e.get_paths_ref().map(|r| r.map(|v| v.len()))),
+        12 => drop(e.get_dht()),
+        13 => drop(e.get_node_info(KEY)),
+        14 => drop(e.get_multicast_interfaces()),
+        15 => drop(e.get_tun()),
+        16 => drop(e.get_tree()),
+        17 => drop(e.stream_tree(drop)),
+        18 => drop(e.get_tree_ref().map(|r| r.map(|v| v.len()))),
+        19 => drop(e.iter_paths().map(|r| r.map(|v| v.count()))),
+        20 => drop(e.iter_tree().map(|r| r.map(|v| v.count()))),
+        21 => {
+            let uri = "tcp://[::1]:1?password=secret".into();
+            drop(e.request_raw("addpeer", HashMap::from([("uri".into(), uri)])))
+        }
+        _ => drop(e.list()),
+    }
+});
@@ -0,0 +1,27 @@
+//! Endpoint performing I/O with `AsyncRead` and `AsyncWrite` traits of `tokio` or `futures`.
+//!
+//! Compiled from the same source as `blocking`, so both can be used in the same build.
+
+use maybe_async::must_be_async as maybe_async;
+
+#[cfg(feature = "use_tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[cfg(feature = "use_futures")]
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Keep items of the shared source only in this copy or only in the other one
+macro_rules! if_blocking {
+    ($($item:tt)*) => {};
+}
+macro_rules! if_async {
+    ($($item:tt)*) => { $($item)* };
+}
+
+#[cfg(any(feature = "use_async_std", feature = "use_smol"))]
+mod connect;
+// Same source is compiled for each runtime
+#[allow(clippy::duplicate_mod)]
+#[path = "endpoint/mod.rs"]
+mod endpoint;
+pub use endpoint::*;
@@ -0,0 +1,41 @@
+//! Shortcuts connecting to the admin socket with streams of specific runtimes.
+
+use super::Endpoint;
+use std::io;
+
+#[cfg(all(feature = "use_async_std", unix))]
+impl Endpoint<async_std::os::unix::net::UnixStream> {
+    /// Connect to a Unix socket, e.g. `/var/run/yggdrasil.sock`, and `attach` to it.
+    pub async fn connect_unix(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let path = async_std::path::Path::new(path.as_ref().as_os_str());
+        let socket = async_std::os::unix::net::UnixStream::connect(path).await?;
+        Ok(Self::attach(socket).await)
+    }
+}
+
+#[cfg(feature = "use_async_std")]
+impl Endpoint<async_std::net::TcpStream> {
+    /// Connect to `AdminListen` of `tcp://` kind and `attach` to it.
+    pub async fn connect_tcp(addr: impl async_std::net::ToSocketAddrs) -> io::Result<Self> {
+        let socket = async_std::net::TcpStream::connect(addr).await?;
+        Ok(Self::attach(socket).await)
+    }
+}
+
+#[cfg(all(feature = "use_smol", unix))]
+impl Endpoint<smol::net::unix::UnixStream> {
+    /// Connect to a Unix socket, e.g. `/var/run/yggdrasil.sock`, and `attach` to it.
+    pub async fn connect_unix(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let socket = smol::net::unix::UnixStream::connect(path).await?;
+        Ok(Self::attach(socket).await)
+    }
+}
+
+#[cfg(feature = "use_smol")]
+impl Endpoint<smol::net::TcpStream> {
+    /// Connect to `AdminListen` of `tcp://` kind and `attach` to it.
+    pub async fn connect_tcp(addr: impl smol::net::AsyncToSocketAddrs) -> io::Result<Self> {
+        let socket = smol::net::TcpStream::connect(addr).await?;
+        Ok(Self::attach(socket).await)
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    process::ExitCode,
+};
+
+#[cfg(unix)]
+use std::{
+    net::TcpListener,
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+};
+
+#[cfg(unix)]
+use yggdrasilctl::proxy::{Policy, Proxy};
+use yggdrasilctl::{config::Config, Endpoint, NodeInfoResolver};
+
+const USAGE: &str = "\
+Usage: yggdrasilctl-tools [-endpoint <uri>] <command> [options]
+
+Options:
+  -endpoint <uri>       Admin socket, `unix:///path` or `tcp://host:port`
+                        (default: `AdminListen` of the config if read,
+                        otherwise unix:///var/run/yggdrasil.sock,
+                        or tcp://localhost:9001 on Windows)
+
+Commands:
+  hosts                 Print names published in node info as hosts-file lines
+    -zone               Print an RFC 1035 zone fragment instead
+    -origin <name>      Zone `$ORIGIN`
+    -ttl <seconds>      Zone `$TTL`
+    -cache <file>       Node info cache to reuse between runs
+    -concurrency <n>    Number of concurrent lookups (default: 8)
+  drift                 Compare config with the running router, exit with 2 if they differ
+    -config <file>      Config file (default: /etc/yggdrasil.conf)
+    -json               Print report as JSON
+  proxy                 Serve the Admin API to other users, forwarding allowed requests (Unix only)
+    -listen <uri>       Socket to listen on, `unix:///path` or `tcp://host:port`
+    -policy <file>      Access rules, one per line, e.g. `uid 0 allow *`, `allow get* list`
+                        (default: allow `get*` and `list` to everyone)
+    -cache <seconds>    Serve getself, getpeers, getsessions, getpaths, gettree
+                        and gettun from a cache refreshed at this interval
+";
+
+#[cfg(unix)]
+const DEFAULT_ENDPOINT: &str = "unix:///var/run/yggdrasil.sock";
+// Default `AdminListen` of the router on other platforms
+#[cfg(not(unix))]
+const DEFAULT_ENDPOINT: &str = "tcp://localhost:9001";
+
+/// Admin socket of either kind
+enum Socket {
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Socket::Unix(s) => s.read(buf),
+            Socket::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Socket::Unix(s) => s.write(buf),
+            Socket::Tcp(s) => s.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Socket::Unix(s) => s.flush(),
+            Socket::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+fn connect(uri: &str) -> Result<Socket, String> {
+    let socket = if let Some(addr) = uri.strip_prefix("tcp://") {
+        TcpStream::connect(addr).map(Socket::Tcp)
+    } else if let Some(path) = uri.strip_prefix("unix://") {
+        #[cfg(unix)]
+        let socket = UnixStream::connect(path).map(Socket::Unix);
+        #[cfg(not(unix))]
+        let socket = Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unix sockets are not supported on this platform: {path:?}"),
+        ));
+        socket
+    } else {
+        return Err(format!("Unsupported endpoint {uri:?}"));
+    };
+    socket.map_err(|err| format!("Can't connect to {uri:?}: {err}"))
+}
+
+struct Args(std::iter::Peekable<std::env::Args>);
+
+impl Args {
+    fn flag(&mut self, name: &str) -> bool {
+        let matches = self
+            .0
+            .peek()
+            .is_some_and(|arg| arg.trim_start_matches('-') == name && arg.starts_with('-'));
+        if matches {
+            self.0.next();
+        }
+        matches
+    }
+
+    fn value(&mut self, name: &str) -> Result<Option<String>, String> {
+        if !self.flag(name) {
+            return Ok(None);
+        }
+        match self.0.next() {
+            Some(value) => Ok(Some(value)),
+            None => Err(format!("Option -{name} requires a value")),
+        }
+    }
+
+    fn parsed<T: std::str::FromStr>(&mut self, name: &str) -> Result<Option<T>, String> {
+        match self.value(name)? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(format!("Invalid value of -{name}: {value:?}")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        match self.0.next() {
+            Some(arg) => Err(format!("Unexpected argument {arg:?}")),
+            None => Ok(()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, String> {
+    let mut args = Args(std::env::args().peekable());
+    args.0.next();
+
+    let uri = args.value("endpoint")?;
+
+    match args.0.next().as_deref() {
+        Some("hosts") => hosts(uri.as_deref().unwrap_or(DEFAULT_ENDPOINT), args),
+        Some("drift") => drift(uri, args),
+        #[cfg(unix)]
+        Some("proxy") => proxy(uri.unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()), args),
+        Some("help" | "-h" | "-help" | "--help") => {
+            print!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(command) => Err(format!("Unknown command {command:?}\n\n{USAGE}")),
+        None => Err(USAGE.to_string()),
+    }
+}
+
+fn hosts(uri: &str, mut args: Args) -> Result<ExitCode, String> {
+    let (mut zone, mut origin, mut ttl, mut cache, mut concurrency) = (false, None, None, None, 8);
+    while args.0.peek().is_some() {
+        if args.flag("zone") {
+            zone = true;
+        } else if let Some(v) = args.value("origin")? {
+            origin = Some(v);
+        } else if let Some(v) = args.parsed("ttl")? {
+            ttl = Some(v);
+        } else if let Some(v) = args.value("cache")? {
+            cache = Some(v);
+        } else if let Some(v) = args.parsed::<usize>("concurrency")? {
+            concurrency = v.max(1);
+        } else {
+            args.finish()?;
+        }
+    }
+
+    let mut endpoint = Endpoint::attach(connect(uri)?);
+    let nodes = endpoint
+        .get_known_nodes()
+        .map_err(|err| err.to_string())??;
+
+    let mut endpoints = vec![endpoint];
+    for _ in 1..concurrency.min(nodes.len()) {
+        let version = endpoints[0].get_version();
+        endpoints.push(Endpoint::attach_version(connect(uri)?, version));
+    }
+    let mut resolver = NodeInfoResolver::new(endpoints);
+    if let Some(cache) = &cache {
+        match resolver.load_cache(cache) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(format!("Can't load cache {cache:?}: {err}"));
+            }
+            _ => {}
+        }
+    }
+    for key in nodes.keys() {
+        resolver.queue(key);
+    }
+    resolver.resolve().map_err(|err| err.to_string())?;
+    if let Some(cache) = &cache {
+        resolver
+            .save_cache(cache)
+            .map_err(|err| format!("Can't save cache {cache:?}: {err}"))?;
+    }
+
+    let entries = resolver.host_entries(&nodes);
+    if zone {
+        print!(
+            "{}",
+            yggdrasilctl::format_zone(&entries, origin.as_deref(), ttl)
+        );
+    } else {
+        print!("{}", yggdrasilctl::format_hosts(&entries));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn drift(uri: Option<String>, mut args: Args) -> Result<ExitCode, String> {
+    let (mut path, mut json) = ("/etc/yggdrasil.conf".to_string(), false);
+    while args.0.peek().is_some() {
+        if args.flag("json") {
+            json = true;
+        } else if let Some(v) = args.value("config")? {
+            path = v;
+        } else {
+            args.finish()?;
+        }
+    }
+
+    let config = Config::read(&path).map_err(|err| format!("Can't read {path:?}: {err}"))?;
+    // Fall back to the admin socket the config points to
+    let uri = uri
+        .or_else(|| config.admin_endpoint().map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+
+    let mut endpoint = Endpoint::attach(connect(&uri)?);
+    let report = endpoint
+        .check_drift(&config)
+        .map_err(|err| err.to_string())??;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        for item in &report.items {
+            println!("{item}");
+        }
+        for check in &report.unchecked {
+            eprintln!("Skipped checking {check}, not reported by the router");
+        }
+    }
+    Ok(match report.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(2),
+    })
+}
+
+// Relies on peer credentials of Unix sockets
+#[cfg(unix)]
+fn proxy(uri: String, mut args: Args) -> Result<ExitCode, String> {
+    let (mut listen, mut policy, mut cache) = (None, None, None);
+    while args.0.peek().is_some() {
+        if let Some(v) = args.value("listen")? {
+            listen = Some(v);
+        } else if let Some(v) = args.value("policy")? {
+            policy = Some(v);
+        } else if let Some(v) = args.parsed::<u64>("cache")? {
+            cache = Some(std::time::Duration::from_secs(v));
+        } else {
+            args.finish()?;
+        }
+    }
+    let listen = listen.ok_or("Option -listen is required")?;
+    let policy = match &policy {
+        Some(path) => std::fs::read_to_string(path)
+            .and_then(|text| Policy::parse(&text))
+            .map_err(|err| format!("Can't read {path:?}: {err}"))?,
+        None => Policy::read_only(),
+    };
+
+    let mut proxy = Proxy::new(move || connect(&uri).map_err(io::Error::other), policy);
+    proxy.set_cache_interval(cache);
+    proxy.on_denied(|request, uid| match uid {
+        Some(uid) => eprintln!("Denied {request:?} to user {uid}"),
+        None => eprintln!("Denied {request:?}"),
+    });
+    let result = if let Some(path) = listen.strip_prefix("unix://") {
+        let listener =
+            UnixListener::bind(path).map_err(|err| format!("Can't listen on {listen:?}: {err}"))?;
+        // Access is controlled by the policy
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o666))
+            .map_err(|err| format!("Can't set permissions of {path:?}: {err}"))?;
+        proxy.serve_unix(&listener)
+    } else if let Some(addr) = listen.strip_prefix("tcp://") {
+        let listener =
+            TcpListener::bind(addr).map_err(|err| format!("Can't listen on {listen:?}: {err}"))?;
+        proxy.serve_tcp(&listener)
+    } else {
+        return Err(format!("Unsupported endpoint {listen:?}"));
+    };
+    result.map_err(|err| err.to_string())?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,20 @@
+//! Endpoint performing blocking I/O with `Read` and `Write` traits of `std`.
+//!
+//! Compiled from the same source as `r#async`, so both can be used in the same build.
+
+use maybe_async::must_be_sync as maybe_async;
+use std::io::{Read as AsyncRead, Write as AsyncWrite};
+
+// Keep items of the shared source only in this copy or only in the other one
+macro_rules! if_blocking {
+    ($($item:tt)*) => { $($item)* };
+}
+macro_rules! if_async {
+    ($($item:tt)*) => {};
+}
+
+// Same source is compiled for each runtime
+#[allow(clippy::duplicate_mod)]
+#[path = "endpoint/mod.rs"]
+mod endpoint;
+pub use endpoint::*;
@@ -0,0 +1,538 @@
+//! Typed access to the [`yggdrasil-go`] configuration file.
+//!
+//! Both Hjson (as generated by `yggdrasil -genconf`) and JSON files are accepted.
+//! [`Config::write`] writes JSON, which is valid Hjson too, so comments don't survive the round-trip.
+//! [`ConfigFile`] instead edits only the peer lists in place, keeping the rest of the file intact.
+//! Keys not covered by [`Config`] are preserved in `extra` fields.
+//!
+//! [`yggdrasil-go`]: https://github.com/yggdrasil-network/yggdrasil-go
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Error, ErrorKind},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+mod drift;
+mod hjson;
+pub use drift::*;
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Config {
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// Peers to connect through a specific network interface, keyed by interface name
+    #[serde(default)]
+    pub interface_peers: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub listen: Vec<String>,
+    /// Admin socket URI, or `none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_listen: Option<String>,
+    #[serde(default, deserialize_with = "parse_multicast_interfaces")]
+    pub multicast_interfaces: Vec<MulticastInterface>,
+    #[serde(default)]
+    pub allowed_public_keys: Vec<String>,
+    /// TUN interface name, `auto` or `none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_name: Option<String>,
+    #[serde(rename = "IfMTU")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_mtu: Option<u64>,
+    #[serde(default)]
+    pub node_info: Map<String, Value>,
+    /// Hex-encoded Ed25519 private key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MulticastInterface {
+    /// Regular expression matching interface names
+    pub regex: String,
+    #[serde(default = "default_true")]
+    pub beacon: bool,
+    #[serde(default = "default_true")]
+    pub listen: bool,
+    #[serde(default)]
+    pub port: u16,
+    #[serde(default)]
+    pub priority: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub password: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Routers before v0.4.0 list bare regular expressions
+fn parse_multicast_interfaces<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<MulticastInterface>, D::Error> {
+    Vec::<Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|v| match v {
+            Value::String(regex) => Ok(MulticastInterface {
+                regex,
+                beacon: true,
+                listen: true,
+                port: 0,
+                priority: 0,
+                password: String::new(),
+                extra: Map::new(),
+            }),
+            v => serde_json::from_value(v).map_err(serde::de::Error::custom),
+        })
+        .collect()
+}
+
+impl Config {
+    /// Parse Hjson or JSON config.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        serde_json::from_value(hjson::parse(text)?)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!("Invalid config: {err}")))
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Serialize config as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("Config is always serializable");
+        json.push('\n');
+        json
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    /// Admin socket URI unless admin socket is disabled.
+    pub fn admin_endpoint(&self) -> Option<&str> {
+        self.admin_listen
+            .as_deref()
+            .filter(|uri| !uri.is_empty() && *uri != "none")
+    }
+
+    /// Mirror `Endpoint::add_peer`. Returns `false` if the peer is already configured.
+    pub fn add_peer(&mut self, uri: &str, interface: Option<&str>) -> bool {
+        let peers = match interface {
+            Some(interface) => self
+                .interface_peers
+                .entry(interface.to_string())
+                .or_default(),
+            None => &mut self.peers,
+        };
+        if peers.iter().any(|p| p == uri) {
+            return false;
+        }
+        peers.push(uri.to_string());
+        true
+    }
+
+    /// Mirror `Endpoint::remove_peer`. Returns `false` if the peer wasn't configured.
+    pub fn remove_peer(&mut self, uri: &str, interface: Option<&str>) -> bool {
+        let peers = match interface {
+            Some(interface) => match self.interface_peers.get_mut(interface) {
+                Some(peers) => peers,
+                None => return false,
+            },
+            None => &mut self.peers,
+        };
+        let len = peers.len();
+        peers.retain(|p| p != uri);
+        let removed = peers.len() != len;
+        if let Some(interface) = interface {
+            if self.interface_peers[interface].is_empty() {
+                self.interface_peers.remove(interface);
+            }
+        }
+        removed
+    }
+}
+
+/// Config file kept in sync with runtime peer changes,
+/// see `Endpoint::add_peer_persistent` and `Endpoint::remove_peer_persistent`.
+///
+/// Only `Peers` and `InterfacePeers` are rewritten, comments and the rest of the file are left intact.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// Keep the previous version as `<path>.bak`
+    pub backup: bool,
+    /// Only report the change, leaving both the router and the file intact
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    /// `false` if config already was in the desired state
+    pub changed: bool,
+    /// Changed lines of the config file, prefixed with `-` or `+`
+    pub diff: String,
+}
+
+impl ConfigFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            backup: true,
+            dry_run: false,
+        }
+    }
+
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn add_peer(&self, uri: &str, interface: Option<&str>) -> io::Result<ConfigChange> {
+        self.update(|c| c.add_peer(uri, interface))
+    }
+
+    pub fn remove_peer(&self, uri: &str, interface: Option<&str>) -> io::Result<ConfigChange> {
+        self.update(|c| c.remove_peer(uri, interface))
+    }
+
+    /// Read the file and apply the change, returning the new contents.
+    pub(crate) fn plan(
+        &self,
+        apply: impl FnOnce(&mut Config) -> bool,
+    ) -> io::Result<(String, ConfigChange)> {
+        let text = fs::read_to_string(&self.path)?;
+        let old = Config::parse(&text)?;
+        let mut config = old.clone();
+        let changed = apply(&mut config);
+        let new = match changed {
+            true => edit_peers(&text, &old, &config)?,
+            false => text.clone(),
+        };
+        let diff = diff_lines(&text, &new);
+        Ok((new, ConfigChange { changed, diff }))
+    }
+
+    /// Same as `plan`, then replace the file unless it's a dry run.
+    pub(crate) fn update(
+        &self,
+        apply: impl FnOnce(&mut Config) -> bool,
+    ) -> io::Result<ConfigChange> {
+        let (text, change) = self.plan(apply)?;
+        self.commit(&text, &change)?;
+        Ok(change)
+    }
+
+    /// Replace the file atomically, unless it's a dry run or there's nothing to change.
+    fn commit(&self, text: &str, change: &ConfigChange) -> io::Result<()> {
+        if self.dry_run || !change.changed {
+            return Ok(());
+        }
+        let with_suffix = |suffix: &str| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            PathBuf::from(path)
+        };
+        let tmp = with_suffix(".tmp");
+        {
+            let mut file = fs::File::create(&tmp)?;
+            // Config contains the private key, keep its permissions
+            file.set_permissions(fs::metadata(&self.path)?.permissions())?;
+            io::Write::write_all(&mut file, text.as_bytes())?;
+            file.sync_all()?;
+        }
+        if self.backup {
+            fs::copy(&self.path, with_suffix(".bak"))?;
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Rewrite `Peers` and `InterfacePeers` of `text` that differ between `old` and `new`,
+/// following the quoting style of the file.
+fn edit_peers(text: &str, old: &Config, new: &Config) -> io::Result<String> {
+    let (_, spans) = hjson::parse_with_members(text)?;
+    let (old_json, new_json) = (serde_json::to_value(old)?, serde_json::to_value(new)?);
+    let json_style = spans.members.first().is_some_and(|m| m.quoted);
+    let indent_of = |pos: usize| -> &str {
+        let line = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let len = text[line..].len() - text[line..].trim_start_matches([' ', '\t']).len();
+        &text[line..line + len]
+    };
+
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for key in ["Peers", "InterfacePeers"] {
+        let value = &new_json[key];
+        if old_json[key] == *value {
+            continue;
+        }
+        // Later duplicates take precedence when parsing
+        match spans.members.iter().rfind(|m| m.key == key) {
+            Some(member) => {
+                let old = &text[member.value.clone()];
+                let empty = old.trim_matches(|c: char| "[]{}".contains(c) || c.is_whitespace());
+                // Empty values don't tell the style, follow the keys then
+                let quoteless = match empty.is_empty() {
+                    true => !json_style,
+                    false => !old.contains('"'),
+                };
+                let indent = indent_of(member.value.start);
+                edits.push((member.value.clone(), render(value, indent, quoteless)));
+            }
+            None => {
+                let indent = match spans.members.last() {
+                    Some(last) => {
+                        let rest = text[last.value.end..spans.end].trim_start();
+                        if json_style && !rest.starts_with(',') {
+                            edits.push((last.value.end..last.value.end, ",".to_string()));
+                        }
+                        indent_of(last.value.start)
+                    }
+                    // Inside root braces
+                    None if spans.end < text.len() => "  ",
+                    None => "",
+                };
+                let newline = match text[..spans.end]
+                    .trim_end_matches([' ', '\t'])
+                    .ends_with('\n')
+                {
+                    true => "",
+                    false => "\n",
+                };
+                let key = match json_style {
+                    true => format!("\"{key}\""),
+                    false => key.to_string(),
+                };
+                let value = render(value, indent, !json_style);
+                edits.push((
+                    spans.end..spans.end,
+                    format!("{newline}{indent}{key}: {value}\n"),
+                ));
+            }
+        }
+    }
+
+    edits.sort_by_key(|(range, _)| range.start);
+    let (mut out, mut pos) = (String::new(), 0);
+    for (range, replacement) in edits {
+        out.push_str(&text[pos..range.start]);
+        out.push_str(&replacement);
+        pos = range.end;
+    }
+    out.push_str(&text[pos..]);
+
+    // Never write a file that reads back differently
+    if Config::parse(&out).ok().as_ref() != Some(new) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Can't update peers without changing the rest of the config",
+        ));
+    }
+    Ok(out)
+}
+
+/// Format `value` as Hjson starting at a line indented with `indent`.
+fn render(value: &Value, indent: &str, quoteless: bool) -> String {
+    let inner = format!("{indent}  ");
+    let separator = if quoteless { "" } else { "," };
+    match value {
+        Value::Array(vec) if !vec.is_empty() => {
+            let elements: Vec<String> = vec
+                .iter()
+                .map(|v| format!("{inner}{}", render(v, &inner, quoteless)))
+                .collect();
+            format!("[\n{}\n{indent}]", elements.join(&format!("{separator}\n")))
+        }
+        Value::Object(map) if !map.is_empty() => {
+            let members: Vec<String> = map
+                .iter()
+                .map(|(k, v)| {
+                    let bare = !k.is_empty()
+                        && k.chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+                    let key = match quoteless && bare {
+                        true => k.clone(),
+                        false => Value::String(k.clone()).to_string(),
+                    };
+                    format!("{inner}{key}: {}", render(v, &inner, quoteless))
+                })
+                .collect();
+            format!(
+                "{{\n{}\n{indent}}}",
+                members.join(&format!("{separator}\n"))
+            )
+        }
+        Value::String(string) if quoteless && is_quoteless(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Whether `string` reads back the same when written without quotes.
+fn is_quoteless(string: &str) -> bool {
+    !string.is_empty()
+        && string == string.trim()
+        && !string.contains(['\n', '\r'])
+        && !string.starts_with(['"', '\'', '#', ',', ':', '[', ']', '{', '}'])
+        && !string.starts_with("//")
+        && !string.starts_with("/*")
+        && !matches!(string, "true" | "false" | "null")
+        && serde_json::from_str::<serde_json::Number>(string).is_err()
+}
+
+/// Line diff based on the longest common subsequence.
+fn diff_lines(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j, mut out) = (0, 0, String::new());
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config() {
+        let text = r#"
+{
+  # List of connection strings for outbound peer connections
+  Peers: [
+    tls://[::1]:1234
+  ]
+  InterfacePeers: {}
+  Listen: []
+  AdminListen: unix:///var/run/yggdrasil.sock
+  MulticastInterfaces:
+  [
+    {
+      Regex: .*
+      Beacon: true
+      Listen: true
+      Port: 0
+      Priority: 0
+      Password: ""
+    }
+  ]
+  AllowedPublicKeys: []
+  IfName: auto
+  IfMTU: 65535
+  NodeInfoPrivacy: false
+  NodeInfo: {
+    name: node
+  }
+  PrivateKey: 00
+}
+"#;
+        let mut config = Config::parse(text).unwrap();
+        assert_eq!(config.peers, ["tls://[::1]:1234"]);
+        assert_eq!(
+            config.admin_endpoint(),
+            Some("unix:///var/run/yggdrasil.sock")
+        );
+        assert_eq!(config.multicast_interfaces[0].regex, ".*");
+        assert_eq!(config.if_name.as_deref(), Some("auto"));
+        assert_eq!(config.if_mtu, Some(65535));
+        assert_eq!(config.node_info["name"], "node");
+        // Quoteless `00` isn't a valid number
+        assert_eq!(config.private_key.as_deref(), Some("00"));
+        assert_eq!(config.extra["NodeInfoPrivacy"], false);
+
+        assert!(config.add_peer("tcp://a:1", Some("eth0")));
+        assert!(!config.add_peer("tcp://a:1", Some("eth0")));
+        assert_eq!(Config::parse(&config.to_json()).unwrap(), config);
+        assert!(config.remove_peer("tcp://a:1", Some("eth0")));
+        assert!(config.interface_peers.is_empty());
+
+        let legacy = Config::parse(r#"{ "MulticastInterfaces": [ "eth0" ] }"#).unwrap();
+        assert!(legacy.multicast_interfaces[0].beacon);
+    }
+
+    #[test]
+    fn config_file() {
+        let dir = std::env::temp_dir().join(format!("yggdrasilctl-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("yggdrasil.conf");
+        let text = "{\n  # Outbound peers\n  Peers: [\n    tcp://a:1\n  ]\n  IfName: auto\n}\n";
+        fs::write(&path, text).unwrap();
+
+        let file = ConfigFile::new(&path).dry_run(true);
+        let change = file.add_peer("tls://[::1]:2", None).unwrap();
+        assert!(change.changed);
+        assert_eq!(change.diff, "+    tls://[::1]:2\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+        // Comments and the rest of the file are kept
+        let file = file.dry_run(false);
+        assert!(file.add_peer("tls://[::1]:2", None).unwrap().changed);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  # Outbound peers\n  Peers: [\n    tcp://a:1\n    tls://[::1]:2\n  ]\n  IfName: auto\n}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(path.with_extension("conf.bak")).unwrap(),
+            text
+        );
+        assert!(!file.remove_peer("tcp://c:3", None).unwrap().changed);
+
+        // Missing keys are appended
+        let change = file.add_peer("tcp://b:2", Some("eth0")).unwrap();
+        assert_eq!(
+            change.diff,
+            "+  InterfacePeers: {\n+    eth0: [\n+      tcp://b:2\n+    ]\n+  }\n"
+        );
+        assert!(file.remove_peer("tcp://b:2", Some("eth0")).unwrap().changed);
+        assert!(file.remove_peer("tcp://a:1", None).unwrap().changed);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  # Outbound peers\n  Peers: [\n    tls://[::1]:2\n  ]\n  IfName: auto\n  InterfacePeers: {}\n}\n"
+        );
+
+        // JSON stays JSON
+        fs::write(&path, "{\n  \"Peers\": [],\n  \"IfName\": \"auto\"\n}").unwrap();
+        file.add_peer("tcp://b:2", Some("eth0")).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap(),
+            serde_json::json!({ "Peers": [], "IfName": "auto", "InterfacePeers": { "eth0": ["tcp://b:2"] } })
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+use super::*;
+use crate::{PeerEntry, TunEntry};
+
+/// Differences between the config and the running router.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct DriftReport {
+    pub items: Vec<DriftItem>,
+    /// Checks that were skipped, as the router doesn't report what they need
+    pub unchecked: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DriftItem {
+    /// Configured peer is not known to the router
+    MissingPeer {
+        uri: String,
+        interface: Option<String>,
+    },
+    /// Configured peer is known to the router, but not connected
+    DownPeer {
+        uri: String,
+        interface: Option<String>,
+        last_error: Option<String>,
+    },
+    /// Outbound peer not present in the config, e.g. added with `addpeer`
+    ExtraPeer { uri: String },
+    /// Interface used for multicast doesn't match any of `MulticastInterfaces`
+    ExtraMulticastInterface { interface: String },
+    /// Entry of `MulticastInterfaces` with `Beacon` or `Listen` doesn't match any interface in use
+    UnusedMulticastInterface { regex: String },
+    /// `MulticastInterfaces` entry is not a valid regular expression
+    InvalidMulticastRegex { regex: String, error: String },
+    IfNameMismatch {
+        configured: String,
+        runtime: Option<String>,
+    },
+    IfMtuMismatch {
+        configured: u64,
+        runtime: Option<u64>,
+    },
+}
+
+impl DriftReport {
+    /// No differences found, regardless of skipped checks.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Compare `config` against the runtime state.
+    /// Checks of `peers`, `multicast_interfaces` and `tun` are skipped when they are `None`.
+    ///
+    /// `remote` of `peers` must be the URI as configured, which only routers since v0.5.0 report.
+    pub fn compare(
+        config: &Config,
+        peers: Option<&[PeerEntry]>,
+        multicast_interfaces: Option<&[String]>,
+        tun: Option<&TunEntry>,
+    ) -> Self {
+        let mut items = Vec::new();
+        let mut unchecked = Vec::new();
+        match peers {
+            Some(peers) => compare_peers(config, peers, &mut items),
+            None => unchecked.push("peers".to_string()),
+        }
+        if multicast_interfaces.is_none() {
+            unchecked.push("multicast interfaces".to_string());
+        }
+        if tun.is_none() {
+            unchecked.push("TUN".to_string());
+        }
+
+        if let Some(interfaces) = multicast_interfaces {
+            let mut regexes = Vec::new();
+            for entry in &config.multicast_interfaces {
+                if !entry.beacon && !entry.listen {
+                    continue;
+                }
+                match regex_lite::Regex::new(&entry.regex) {
+                    Ok(regex) => regexes.push((entry, regex)),
+                    Err(err) => items.push(DriftItem::InvalidMulticastRegex {
+                        regex: entry.regex.clone(),
+                        error: err.to_string(),
+                    }),
+                }
+            }
+            for interface in interfaces {
+                if !regexes.iter().any(|(_, r)| r.is_match(interface)) {
+                    items.push(DriftItem::ExtraMulticastInterface {
+                        interface: interface.clone(),
+                    });
+                }
+            }
+            for (entry, regex) in regexes {
+                if !interfaces.iter().any(|i| regex.is_match(i)) {
+                    items.push(DriftItem::UnusedMulticastInterface {
+                        regex: entry.regex.clone(),
+                    });
+                }
+            }
+        }
+
+        if let Some(tun) = tun {
+            let runtime = tun.enabled.then(|| tun.name.clone()).flatten();
+            if let Some(configured) = &config.if_name {
+                let matches = match configured.as_str() {
+                    "none" => !tun.enabled,
+                    "auto" => tun.enabled,
+                    name => runtime.as_deref() == Some(name),
+                };
+                if !matches {
+                    items.push(DriftItem::IfNameMismatch {
+                        configured: configured.clone(),
+                        runtime: runtime.clone(),
+                    });
+                }
+            }
+            if let (Some(configured), true) = (config.if_mtu, tun.enabled) {
+                if tun.mtu != Some(configured) {
+                    items.push(DriftItem::IfMtuMismatch {
+                        configured,
+                        runtime: tun.mtu,
+                    });
+                }
+            }
+        }
+
+        Self { items, unchecked }
+    }
+}
+
+fn compare_peers(config: &Config, peers: &[PeerEntry], items: &mut Vec<DriftItem>) {
+    let configured = config.peers.iter().map(|uri| (uri, None)).chain(
+        config
+            .interface_peers
+            .iter()
+            .flat_map(|(interface, uris)| uris.iter().map(move |uri| (uri, Some(interface)))),
+    );
+    let mut matched = vec![false; peers.len()];
+    for (uri, interface) in configured {
+        let normalized = normalize_uri(uri);
+        let found = peers.iter().enumerate().find(|(_, p)| {
+            p.remote
+                .as_deref()
+                .is_some_and(|remote| normalize_uri(remote) == normalized)
+        });
+        let interface = interface.cloned();
+        match found {
+            Some((i, peer)) => {
+                matched[i] = true;
+                if !peer.up {
+                    items.push(DriftItem::DownPeer {
+                        uri: uri.clone(),
+                        interface,
+                        last_error: peer.last_error.clone(),
+                    });
+                }
+            }
+            None => items.push(DriftItem::MissingPeer {
+                uri: uri.clone(),
+                interface,
+            }),
+        }
+    }
+    for (peer, matched) in peers.iter().zip(matched) {
+        let Some(remote) = &peer.remote else {
+            continue;
+        };
+        if matched || peer.inbound || is_link_local(remote) {
+            // Multicast peers are discovered on link-local addresses
+            continue;
+        }
+        items.push(DriftItem::ExtraPeer {
+            uri: remote.clone(),
+        });
+    }
+}
+
+impl std::fmt::Display for DriftItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let on = |interface: &Option<String>| match interface {
+            Some(interface) => format!(" on {interface}"),
+            None => String::new(),
+        };
+        match self {
+            DriftItem::MissingPeer { uri, interface } => {
+                write!(f, "Configured peer {uri}{} is missing", on(interface))
+            }
+            DriftItem::DownPeer {
+                uri,
+                interface,
+                last_error,
+            } => {
+                write!(f, "Configured peer {uri}{} is down", on(interface))?;
+                match last_error {
+                    Some(err) => write!(f, ": {err}"),
+                    None => Ok(()),
+                }
+            }
+            DriftItem::ExtraPeer { uri } => write!(f, "Peer {uri} is not configured"),
+            DriftItem::ExtraMulticastInterface { interface } => {
+                write!(f, "Multicast interface {interface} is not configured")
+            }
+            DriftItem::UnusedMulticastInterface { regex } => {
+                write!(f, "Multicast regex {regex:?} matches no interface in use")
+            }
+            DriftItem::InvalidMulticastRegex { regex, error } => {
+                write!(f, "Multicast regex {regex:?} is invalid: {error}")
+            }
+            DriftItem::IfNameMismatch {
+                configured,
+                runtime,
+            } => write!(
+                f,
+                "IfName is {configured:?}, but TUN is {}",
+                runtime.as_deref().unwrap_or("disabled")
+            ),
+            DriftItem::IfMtuMismatch {
+                configured,
+                runtime,
+            } => match runtime {
+                Some(runtime) => write!(f, "IfMTU is {configured}, but TUN MTU is {runtime}"),
+                None => write!(f, "IfMTU is {configured}, but TUN MTU is unknown"),
+            },
+        }
+    }
+}
+
+/// Compare URIs ignoring case of the scheme and host, and query parameters.
+fn normalize_uri(uri: &str) -> String {
+    let uri = uri.split(['?', '#']).next().unwrap_or(uri);
+    let uri = uri.trim_end_matches('/');
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return uri.to_string();
+    };
+    // Path, e.g. the target of `socks://` peers, is case-sensitive
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let (userinfo, host) = match authority.rsplit_once('@') {
+        Some((userinfo, host)) => (format!("{userinfo}@"), host),
+        None => (String::new(), authority),
+    };
+    format!(
+        "{}://{userinfo}{}{path}",
+        scheme.to_ascii_lowercase(),
+        host.to_ascii_lowercase()
+    )
+}
+
+fn is_link_local(uri: &str) -> bool {
+    let host = uri.split("://").nth(1).unwrap_or(uri);
+    host.trim_start_matches('[')
+        .to_ascii_lowercase()
+        .starts_with("fe80:")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(remote: &str, up: bool, inbound: bool) -> PeerEntry {
+        PeerEntry {
+            address: None,
+            key: String::new(),
+            port: 0,
+            priority: None,
+            remote: Some(remote.to_string()),
+            bytes_recvd: None,
+            bytes_sent: None,
+            uptime: None,
+            up,
+            inbound,
+            latency: None,
+            last_error: None,
+            last_error_time: None,
+            cost: None,
+            rate_recvd: None,
+            rate_sent: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn drift() {
+        let config = Config::parse(
+            r#"
+Peers: [ "tls://a:1?key=00", "tcp://b:2", "tcp://c:3" ]
+MulticastInterfaces: [ { Regex: "eth.*" }, { Regex: "wlan.*" } ]
+IfName: auto
+IfMTU: 65535
+"#,
+        )
+        .unwrap();
+        let peers = [
+            peer("TLS://a:1", true, false),
+            peer("tcp://b:2", false, false),
+            peer("tcp://d:4", true, false),
+            peer("tcp://e:5", true, true),
+            peer("tls://[fe80::1%25eth0]:6", true, false),
+        ];
+        let tun = TunEntry {
+            enabled: true,
+            name: Some("tun0".to_string()),
+            mtu: Some(1500),
+            extra: Default::default(),
+        };
+        let report = DriftReport::compare(
+            &config,
+            Some(&peers),
+            Some(&["eth0".to_string(), "br0".to_string()]),
+            Some(&tun),
+        );
+        assert_eq!(
+            report.items,
+            [
+                DriftItem::DownPeer {
+                    uri: "tcp://b:2".to_string(),
+                    interface: None,
+                    last_error: None
+                },
+                DriftItem::MissingPeer {
+                    uri: "tcp://c:3".to_string(),
+                    interface: None
+                },
+                DriftItem::ExtraPeer {
+                    uri: "tcp://d:4".to_string()
+                },
+                DriftItem::ExtraMulticastInterface {
+                    interface: "br0".to_string()
+                },
+                DriftItem::UnusedMulticastInterface {
+                    regex: "wlan.*".to_string()
+                },
+                DriftItem::IfMtuMismatch {
+                    configured: 65535,
+                    runtime: Some(1500)
+                },
+            ]
+        );
+        assert!(report.unchecked.is_empty());
+
+        let report = DriftReport::compare(&config, None, None, None);
+        assert!(report.is_empty());
+        assert_eq!(report.unchecked, ["peers", "multicast interfaces", "TUN"]);
+
+        assert_eq!(
+            normalize_uri("SOCKS://Proxy:1/Host.example:2?key=00"),
+            "socks://proxy:1/Host.example:2"
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! Minimal [Hjson] reader producing `serde_json::Value`.
+//!
+//! Covers the subset emitted by `yggdrasil -genconf` and commonly written by hand:
+//! comments, quoteless keys and strings, optional commas and root braces,
+//! single-quoted and multiline `'''` strings.
+//!
+//! [Hjson]: https://hjson.github.io/syntax.html
+
+use serde_json::{Map, Number, Value};
+use std::{
+    io::{Error, ErrorKind, Result},
+    ops::Range,
+};
+
+pub fn parse(text: &str) -> Result<Value> {
+    parse_with_members(text).map(|(value, _)| value)
+}
+
+/// Member of the root object, located in the source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub key: String,
+    /// Key is written in quotes, as in JSON
+    pub quoted: bool,
+    pub value: Range<usize>,
+}
+
+/// Root members in order of appearance, and the offset at which more members can be inserted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Members {
+    pub members: Vec<Member>,
+    pub end: usize,
+}
+
+pub fn parse_with_members(text: &str) -> Result<(Value, Members)> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+        spans: Members {
+            members: Vec::new(),
+            end: text.len(),
+        },
+    };
+    parser.skip_whitespace()?;
+    let value = if parser.peek() == Some(b'{') {
+        parser.pos += 1;
+        parser.members(Some(b'}'), true)?
+    } else {
+        // Root braces may be omitted
+        parser.members(None, true)?
+    };
+    parser.skip_whitespace()?;
+    match parser.peek() {
+        None => Ok((value, parser.spans)),
+        Some(_) => Err(parser.error("Trailing characters after the root value")),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    // Root members seen so far
+    spans: Members,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.text.get(self.pos + offset).copied()
+    }
+
+    fn rest(&self) -> &[u8] {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let before = &self.text[..self.pos.min(self.text.len())];
+        let line = before.iter().filter(|&&c| c == b'\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != b'\n').count() + 1;
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{msg} at line {line} column {column}"),
+        )
+    }
+
+    /// Skip whitespace and comments, including newlines.
+    fn skip_whitespace(&mut self) -> Result<()> {
+        loop {
+            match self.peek() {
+                Some(b' ' | b'\t' | b'\r' | b'\n') => self.pos += 1,
+                Some(b'#') => self.skip_line(),
+                Some(b'/') if self.peek_at(1) == Some(b'/') => self.skip_line(),
+                Some(b'/') if self.peek_at(1) == Some(b'*') => {
+                    match self.rest()[2..].windows(2).position(|w| w == b"*/") {
+                        Some(end) => self.pos += end + 4,
+                        None => return Err(self.error("Unterminated comment")),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while !matches!(self.peek(), None | Some(b'\n')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip a separating comma if any.
+    fn skip_separator(&mut self) -> Result<()> {
+        self.skip_whitespace()?;
+        if self.peek() == Some(b',') {
+            self.pos += 1;
+        }
+        self.skip_whitespace()
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                self.members(Some(b'}'), false)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                self.elements()
+            }
+            Some(b'\'') if self.rest().starts_with(b"'''") => self.multiline_string(),
+            Some(q @ (b'"' | b'\'')) => self.quoted_string(q).map(Value::String),
+            Some(b'}' | b']' | b',' | b':') => Err(self.error("Unexpected punctuator")),
+            Some(_) => self.quoteless(),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn members(&mut self, close: Option<u8>, root: bool) -> Result<Value> {
+        let mut map = Map::new();
+        loop {
+            self.skip_whitespace()?;
+            match (self.peek(), close) {
+                (Some(c), Some(close)) if c == close => {
+                    if root {
+                        self.spans.end = self.pos;
+                    }
+                    self.pos += 1;
+                    return Ok(Value::Object(map));
+                }
+                (None, None) => return Ok(Value::Object(map)),
+                (None, Some(_)) => return Err(self.error("Unterminated object")),
+                _ => {}
+            }
+            let quoted = matches!(self.peek(), Some(b'"' | b'\''));
+            let key = self.key()?;
+            self.skip_whitespace()?;
+            if self.peek() != Some(b':') {
+                return Err(self.error("Expected ':'"));
+            }
+            self.pos += 1;
+            self.skip_whitespace()?;
+            let start = self.pos;
+            let value = self.value()?;
+            if root {
+                // Quoteless strings run to the end of the line
+                let trailing = self.text[start..self.pos]
+                    .iter()
+                    .rev()
+                    .take_while(|c| c.is_ascii_whitespace())
+                    .count();
+                self.spans.members.push(Member {
+                    key: key.clone(),
+                    quoted,
+                    value: start..self.pos - trailing,
+                });
+            }
+            map.insert(key, value);
+            self.skip_separator()?;
+        }
+    }
+
+    fn elements(&mut self) -> Result<Value> {
+        let mut vec = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            match self.peek() {
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(vec));
+                }
+                None => return Err(self.error("Unterminated array")),
+                _ => {}
+            }
+            vec.push(self.value()?);
+            self.skip_separator()?;
+        }
+    }
+
+    fn key(&mut self) -> Result<String> {
+        if let Some(q @ (b'"' | b'\'')) = self.peek() {
+            return self.quoted_string(q);
+        }
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if matches!(c, b',' | b':' | b'[' | b']' | b'{' | b'}') || c.is_ascii_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("Expected key"));
+        }
+        Ok(String::from_utf8_lossy(&self.text[start..self.pos]).into_owned())
+    }
+
+    fn quoted_string(&mut self, quote: u8) -> Result<String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => {
+                    self.pos = start;
+                    return Err(self.error("Unterminated string"));
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = self
+                        .peek()
+                        .ok_or_else(|| self.error("Unterminated string"))?;
+                    self.pos += 1;
+                    match c {
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'u' => {
+                            let c = self.unicode_escape()?;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        }
+                        c => out.push(c),
+                    }
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("Invalid UTF-8 in string"))
+    }
+
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) && self.rest().starts_with(b"\\u") {
+            self.pos += 2;
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let code = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("Invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn multiline_string(&mut self) -> Result<Value> {
+        let indent = self.text[..self.pos]
+            .iter()
+            .rev()
+            .take_while(|&&c| c != b'\n')
+            .count();
+        self.pos += 3;
+        let Some(end) = self.rest().windows(3).position(|w| w == b"'''") else {
+            return Err(self.error("Unterminated multiline string"));
+        };
+        let body = String::from_utf8_lossy(&self.rest()[..end]).into_owned();
+        self.pos += end + 3;
+
+        let mut lines: Vec<&str> = body.split('\n').collect();
+        // Ignore text on the lines of the opening and closing quotes if it's blank
+        if lines.len() > 1 && lines[0].trim().is_empty() {
+            lines.remove(0);
+        }
+        if lines.len() > 1 && lines[lines.len() - 1].trim().is_empty() {
+            lines.pop();
+        }
+        let lines: Vec<&str> = lines
+            .into_iter()
+            .map(|line| {
+                let strip = line
+                    .bytes()
+                    .take(indent)
+                    .take_while(|c| c.is_ascii_whitespace())
+                    .count();
+                line[strip..].trim_end_matches('\r')
+            })
+            .collect();
+        Ok(Value::String(lines.join("\n")))
+    }
+
+    /// Keyword, number or a string running to the end of the line.
+    fn quoteless(&mut self) -> Result<Value> {
+        let start = self.pos;
+
+        // Keyword or number followed by a separator
+        let token_end = self
+            .rest()
+            .iter()
+            .position(|&c| matches!(c, b',' | b']' | b'}') || c.is_ascii_whitespace())
+            .map_or(self.text.len(), |len| self.pos + len);
+        let mut after = token_end;
+        while matches!(self.text.get(after), Some(b' ' | b'\t' | b'\r')) {
+            after += 1;
+        }
+        let after = &self.text[after..];
+        if after.is_empty()
+            || matches!(after[0], b'\n' | b',' | b']' | b'}' | b'#')
+            || after.starts_with(b"//")
+            || after.starts_with(b"/*")
+        {
+            let token = std::str::from_utf8(&self.text[start..token_end]).unwrap_or("");
+            let literal = match token {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                "null" => Some(Value::Null),
+                _ => serde_json::from_str::<Number>(token)
+                    .ok()
+                    .map(Value::Number),
+            };
+            if let Some(literal) = literal {
+                self.pos = token_end;
+                return Ok(literal);
+            }
+        }
+
+        self.skip_line();
+        let line = String::from_utf8_lossy(&self.text[start..self.pos]);
+        Ok(Value::String(line.trim_end().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn hjson() {
+        let text = r#"
+# comment
+Peers: [
+  tcp://1.2.3.4:5 # not a comment
+  "tls://[::1]:6" // comment
+]
+/* block
+   comment */
+AdminListen: unix:///var/run/yggdrasil.sock
+IfMTU: 65535, NodeInfoPrivacy: false
+NodeInfo: { name: 'node', 'a b': null }
+Multi:
+  '''
+  first
+    second
+  '''
+Escaped: "é\n"
+"#;
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "Peers": ["tcp://1.2.3.4:5 # not a comment", "tls://[::1]:6"],
+                "AdminListen": "unix:///var/run/yggdrasil.sock",
+                "IfMTU": 65535,
+                "NodeInfoPrivacy": false,
+                "NodeInfo": { "name": "node", "a b": null },
+                "Multi": "first\n  second",
+                "Escaped": "\u{e9}\n",
+            })
+        );
+        assert_eq!(
+            parse("{ \"a\": [1, 2.5, -3] }").unwrap(),
+            json!({ "a": [1, 2.5, -3] })
+        );
+        assert!(parse("{ a: [1, 2 }").is_err());
+
+        let text = "{\n  a: text  \n  \"b\": [1]\n  c: { d: 2 }\n}\n";
+        let (_, members) = parse_with_members(text).unwrap();
+        let spans: Vec<_> = members
+            .members
+            .iter()
+            .map(|m| (m.key.as_str(), m.quoted, &text[m.value.clone()]))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("a", false, "text"),
+                ("b", true, "[1]"),
+                ("c", false, "{ d: 2 }")
+            ]
+        );
+        assert_eq!(&text[members.end..], "}\n");
+    }
+}
@@ -0,0 +1,357 @@
+use super::*;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::SystemTime,
+};
+
+/// State of the router tables captured at a single moment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouterSnapshot {
+    pub time: SystemTime,
+    pub peers: Vec<PeerEntry>,
+    pub sessions: Vec<SessionEntry>,
+    pub paths: Vec<PathEntry>,
+    /// Empty for routers before v0.5.0
+    pub tree: Vec<TreeEntry>,
+}
+
+/// Identifies a peer across snapshots, see `peer_key`.
+pub type PeerKey = (String, u64, Option<String>);
+
+/// Key peers by `(key, port)`, so inbound peers reconnecting from another source port
+/// are still the same peer. Disconnected peers of v0.5 routers have no key, so they
+/// are told apart by `remote` instead.
+pub fn peer_key(entry: &PeerEntry) -> PeerKey {
+    let remote = match entry.key.is_empty() {
+        true => entry.remote.clone(),
+        false => None,
+    };
+    (entry.key.clone(), entry.port, remote)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotDiff {
+    /// Wall-clock time between the snapshots, `None` if the clock went backwards
+    pub elapsed: Option<Duration>,
+    /// Peers keyed by `peer_key`
+    pub peers: Vec<EntryDiff<PeerKey, PeerEntry>>,
+    pub sessions: Vec<EntryDiff<String, SessionEntry>>,
+    pub paths: Vec<EntryDiff<String, PathEntry>>,
+    pub tree: Vec<EntryDiff<String, TreeEntry>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum EntryDiff<K, T> {
+    Added {
+        key: K,
+        entry: T,
+    },
+    Removed {
+        key: K,
+        entry: T,
+    },
+    Changed {
+        key: K,
+        fields: Vec<FieldChange>,
+        /// Only computed for peers and sessions
+        traffic: Option<TrafficDelta>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrafficDelta {
+    /// Bytes received in between, counted from the reconnect if there was one
+    pub bytes_recvd: Option<u64>,
+    pub bytes_sent: Option<u64>,
+    /// Bytes per second
+    pub rate_recvd: Option<f64>,
+    /// Bytes per second
+    pub rate_sent: Option<f64>,
+    /// `uptime` went backwards, so the connection was re-established in between
+    pub reconnected: bool,
+}
+
+/// Traffic counters shared by peer and session entries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Counters {
+    pub bytes_recvd: Option<u64>,
+    pub bytes_sent: Option<u64>,
+    pub uptime: Option<f64>,
+}
+
+impl From<&PeerEntry> for Counters {
+    fn from(e: &PeerEntry) -> Self {
+        Self {
+            bytes_recvd: e.bytes_recvd,
+            bytes_sent: e.bytes_sent,
+            uptime: e.uptime,
+        }
+    }
+}
+
+impl From<&SessionEntry> for Counters {
+    fn from(e: &SessionEntry) -> Self {
+        Self {
+            bytes_recvd: e.bytes_recvd,
+            bytes_sent: e.bytes_sent,
+            uptime: e.uptime,
+        }
+    }
+}
+
+impl Counters {
+    /// Compute traffic since `older` was observed `elapsed` ago.
+    pub fn delta(&self, older: &Counters, elapsed: Option<Duration>) -> TrafficDelta {
+        let reconnected =
+            matches!((older.uptime, self.uptime), (Some(old), Some(new)) if new < old);
+
+        // Prefer router's own clock, fall back to the wall clock
+        let interval = match (older.uptime, self.uptime) {
+            (_, Some(new)) if reconnected => Some(new),
+            (Some(old), Some(new)) => Some(new - old),
+            _ => elapsed.map(|e| e.as_secs_f64()),
+        };
+
+        let bytes = |old: Option<u64>, new: Option<u64>| match (old, new) {
+            // Counters start over after reconnecting
+            (Some(old), Some(new)) if !reconnected && new >= old => Some(new - old),
+            (Some(_), Some(new)) => Some(new),
+            _ => None,
+        };
+        let rate = |bytes: Option<u64>| match (bytes, interval) {
+            (Some(bytes), Some(interval)) if interval > 0.0 => Some(bytes as f64 / interval),
+            _ => None,
+        };
+
+        let bytes_recvd = bytes(older.bytes_recvd, self.bytes_recvd);
+        let bytes_sent = bytes(older.bytes_sent, self.bytes_sent);
+        TrafficDelta {
+            bytes_recvd,
+            bytes_sent,
+            rate_recvd: rate(bytes_recvd),
+            rate_sent: rate(bytes_sent),
+            reconnected,
+        }
+    }
+}
+
+impl RouterSnapshot {
+    /// Compare with a `newer` snapshot of the same router.
+    pub fn diff(&self, newer: &RouterSnapshot) -> SnapshotDiff {
+        let elapsed = newer.time.duration_since(self.time).ok();
+        SnapshotDiff {
+            elapsed,
+            peers: diff_entries(&self.peers, &newer.peers, peer_key, |old, new| {
+                Some(Counters::from(new).delta(&old.into(), elapsed))
+            }),
+            sessions: diff_entries(
+                &self.sessions,
+                &newer.sessions,
+                |e| e.key.clone(),
+                |old, new| Some(Counters::from(new).delta(&old.into(), elapsed)),
+            ),
+            paths: diff_entries(&self.paths, &newer.paths, |e| e.key.clone(), |_, _| None),
+            tree: diff_entries(&self.tree, &newer.tree, |e| e.key.clone(), |_, _| None),
+        }
+    }
+}
+
+fn diff_entries<K: Ord + Clone, T: Serialize + Clone>(
+    old: &[T],
+    new: &[T],
+    key: impl Fn(&T) -> K,
+    traffic: impl Fn(&T, &T) -> Option<TrafficDelta>,
+) -> Vec<EntryDiff<K, T>> {
+    let old: BTreeMap<K, &T> = old.iter().map(|e| (key(e), e)).collect();
+    let mut new: BTreeMap<K, &T> = new.iter().map(|e| (key(e), e)).collect();
+
+    let mut diff = Vec::new();
+    for (key, old) in old {
+        let Some(new) = new.remove(&key) else {
+            diff.push(EntryDiff::Removed {
+                key,
+                entry: old.clone(),
+            });
+            continue;
+        };
+        let fields = diff_fields(old, new);
+        if !fields.is_empty() {
+            diff.push(EntryDiff::Changed {
+                key,
+                fields,
+                traffic: traffic(old, new),
+            });
+        }
+    }
+    for (key, new) in new {
+        diff.push(EntryDiff::Added {
+            key,
+            entry: new.clone(),
+        });
+    }
+    diff
+}
+
+fn diff_fields<T: Serialize>(old: &T, new: &T) -> Vec<FieldChange> {
+    let (Ok(Value::Object(mut old)), Ok(Value::Object(mut new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    let fields: BTreeSet<String> = old.keys().chain(new.keys()).cloned().collect();
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let old = old.remove(&field).unwrap_or(Value::Null);
+            let new = new.remove(&field).unwrap_or(Value::Null);
+            (old != new).then_some(FieldChange { field, old, new })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(key: &str, bytes_recvd: u64, uptime: f64) -> SessionEntry {
+        SessionEntry {
+            address: Ipv6Addr::LOCALHOST,
+            key: key.to_string(),
+            bytes_recvd: Some(bytes_recvd),
+            bytes_sent: Some(0),
+            uptime: Some(uptime),
+            rate_recvd: None,
+            rate_sent: None,
+            extra: Default::default(),
+        }
+    }
+
+    fn snapshot(secs: u64, sessions: Vec<SessionEntry>) -> RouterSnapshot {
+        RouterSnapshot {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            peers: Vec::new(),
+            sessions,
+            paths: Vec::new(),
+            tree: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn sessions() {
+        let old = snapshot(0, vec![session("a", 100, 10.0), session("b", 100, 10.0)]);
+        let new = snapshot(10, vec![session("a", 1100, 20.0), session("c", 0, 1.0)]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.elapsed, Some(Duration::from_secs(10)));
+        assert_eq!(diff.sessions.len(), 3);
+        let EntryDiff::Changed {
+            key,
+            fields,
+            traffic: Some(traffic),
+        } = &diff.sessions[0]
+        else {
+            panic!("Unexpected diff {:?}", diff.sessions[0]);
+        };
+        assert_eq!(key, "a");
+        assert_eq!(
+            fields.iter().map(|f| f.field.as_str()).collect::<Vec<_>>(),
+            ["bytes_recvd", "uptime"]
+        );
+        assert_eq!(traffic.bytes_recvd, Some(1000));
+        assert_eq!(traffic.rate_recvd, Some(100.0));
+        assert!(!traffic.reconnected);
+        assert!(matches!(&diff.sessions[1], EntryDiff::Removed { key, .. } if key == "b"));
+        assert!(matches!(&diff.sessions[2], EntryDiff::Added { key, .. } if key == "c"));
+    }
+
+    fn peer(key: &str, remote: &str) -> PeerEntry {
+        PeerEntry {
+            address: None,
+            key: key.to_string(),
+            port: 0,
+            priority: None,
+            remote: Some(remote.to_string()),
+            bytes_recvd: None,
+            bytes_sent: None,
+            uptime: None,
+            up: false,
+            inbound: false,
+            latency: Some(Duration::from_millis(15)),
+            last_error: None,
+            last_error_time: None,
+            cost: None,
+            rate_recvd: None,
+            rate_sent: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn peers() {
+        let mut old = snapshot(0, Vec::new());
+        old.peers = vec![peer("", "tls://a:1"), peer("", "tls://b:1")];
+        let mut new = snapshot(10, Vec::new());
+        new.peers = vec![peer("", "tls://a:1"), peer("", "tls://c:1")];
+        let diff = old.diff(&new);
+        assert_eq!(diff.peers.len(), 2);
+        assert!(
+            matches!(&diff.peers[0], EntryDiff::Removed { key, .. } if key.2.as_deref() == Some("tls://b:1"))
+        );
+        assert!(
+            matches!(&diff.peers[1], EntryDiff::Added { key, .. } if key.2.as_deref() == Some("tls://c:1"))
+        );
+
+        // Serialized durations keep serde's format and are read back
+        let json = serde_json::to_value(&new).unwrap();
+        assert_eq!(
+            json["peers"][0]["latency"],
+            serde_json::json!({ "secs": 0, "nanos": 15_000_000 })
+        );
+        assert_eq!(serde_json::from_value::<RouterSnapshot>(json).unwrap(), new);
+    }
+
+    #[test]
+    fn inbound_reconnect() {
+        let inbound = |remote: &str, bytes_recvd, uptime| PeerEntry {
+            inbound: true,
+            up: true,
+            bytes_recvd: Some(bytes_recvd),
+            uptime: Some(uptime),
+            ..peer("a", remote)
+        };
+        let mut old = snapshot(0, Vec::new());
+        old.peers = vec![inbound("tls://[::1]:50001", 1000, 100.0)];
+        let mut new = snapshot(60, Vec::new());
+        new.peers = vec![inbound("tls://[::1]:50002", 50, 5.0)];
+        let diff = old.diff(&new);
+        assert_eq!(diff.peers.len(), 1);
+        let EntryDiff::Changed {
+            key,
+            traffic: Some(traffic),
+            ..
+        } = &diff.peers[0]
+        else {
+            panic!("Unexpected diff {:?}", diff.peers[0]);
+        };
+        assert_eq!(key, &("a".to_string(), 0, None));
+        assert!(traffic.reconnected);
+        assert_eq!(traffic.bytes_recvd, Some(50));
+    }
+
+    #[test]
+    fn reconnect() {
+        let old = Counters::from(&session("a", 1000, 100.0));
+        let new = Counters::from(&session("a", 50, 5.0));
+        let delta = new.delta(&old, Some(Duration::from_secs(60)));
+        assert!(delta.reconnected);
+        assert_eq!(delta.bytes_recvd, Some(50));
+        assert_eq!(delta.rate_recvd, Some(10.0));
+    }
+}
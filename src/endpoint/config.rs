@@ -0,0 +1,71 @@
+use super::*;
+use crate::config::{Config, ConfigChange, ConfigFile, DriftReport};
+
+impl<S: AsyncWrite + AsyncRead + Unpin> Endpoint<S> {
+    /// Perform `add_peer` and, if router accepted it, add the peer to `config` too.
+    ///
+    /// The file is read again once the router responds, so edits made in between are kept.
+    #[maybe_async]
+    pub async fn add_peer_persistent(
+        &mut self,
+        uri: String,
+        interface: Option<String>,
+        config: &ConfigFile,
+    ) -> RequestResult<ConfigChange> {
+        // Make sure config is usable before changing the router
+        let (_, change) = config.plan(|c| c.add_peer(&uri, interface.as_deref()))?;
+        if config.dry_run {
+            return Ok(Ok(change));
+        }
+        if let Err(err) = self.add_peer(uri.clone(), interface.clone()).await? {
+            return Ok(Err(err));
+        }
+        Ok(Ok(config.add_peer(&uri, interface.as_deref())?))
+    }
+
+    /// Perform `remove_peer` and, if router accepted it, remove the peer from `config` too.
+    ///
+    /// The file is read again once the router responds, so edits made in between are kept.
+    #[maybe_async]
+    pub async fn remove_peer_persistent(
+        &mut self,
+        uri: String,
+        interface: Option<String>,
+        config: &ConfigFile,
+    ) -> RequestResult<ConfigChange> {
+        let (_, change) = config.plan(|c| c.remove_peer(&uri, interface.as_deref()))?;
+        if config.dry_run {
+            return Ok(Ok(change));
+        }
+        if let Err(err) = self.remove_peer(uri.clone(), interface.clone()).await? {
+            return Ok(Err(err));
+        }
+        Ok(Ok(config.remove_peer(&uri, interface.as_deref())?))
+    }
+}
+
+impl<S: AsyncWrite + AsyncRead + Unpin> Endpoint<S> {
+    /// Compare `config` against the state of the router.
+    ///
+    /// Multicast and TUN checks are skipped if the router doesn't support the requests.
+    /// Peer checks are skipped for routers before v0.5.0, which report resolved addresses
+    /// of peers instead of configured URIs.
+    #[maybe_async]
+    pub async fn check_drift(&mut self, config: &Config) -> RequestResult<DriftReport> {
+        let peers = match self.router_version {
+            RouterVersion::v0_5_0__ => match self.get_peers().await? {
+                Ok(peers) => Some(peers),
+                Err(err) => return Ok(Err(err)),
+            },
+            _ => None,
+        };
+        let multicast_interfaces = self.get_multicast_interfaces().await?.ok();
+        let tun = self.get_tun().await?.ok();
+        Ok(Ok(DriftReport::compare(
+            config,
+            peers.as_deref(),
+            multicast_interfaces.as_deref(),
+            tun.as_ref(),
+        )))
+    }
+}
@@ -0,0 +1,36 @@
+use super::*;
+use crate::RouterSnapshot;
+use std::time::SystemTime;
+
+impl<S: AsyncWrite + AsyncRead + Unpin> Endpoint<S> {
+    #[maybe_async]
+    pub async fn get_snapshot(&mut self) -> RequestResult<RouterSnapshot> {
+        let time = SystemTime::now();
+        let peers = match self.get_peers().await? {
+            Ok(peers) => peers,
+            Err(err) => return Ok(Err(err)),
+        };
+        let sessions = match self.get_sessions().await? {
+            Ok(sessions) => sessions,
+            Err(err) => return Ok(Err(err)),
+        };
+        let paths = match self.get_paths().await? {
+            Ok(paths) => paths,
+            Err(err) => return Ok(Err(err)),
+        };
+        let tree = match self.router_version {
+            RouterVersion::v0_5_0__ => match self.get_tree().await? {
+                Ok(tree) => tree,
+                Err(err) => return Ok(Err(err)),
+            },
+            _ => Vec::new(),
+        };
+        Ok(Ok(RouterSnapshot {
+            time,
+            peers,
+            sessions,
+            paths,
+            tree,
+        }))
+    }
+}
@@ -0,0 +1,60 @@
+use super::*;
+use crate::{
+    hosts::{disambiguate, sanitize_hostname},
+    HostEntry,
+};
+use std::net::Ipv6Addr;
+
+impl<S: AsyncWrite + AsyncRead + Unpin> Endpoint<S> {
+    /// Collect addresses of the local node and nodes seen in sessions, peers and tree,
+    /// keyed by their public keys.
+    #[maybe_async]
+    pub async fn get_known_nodes(&mut self) -> RequestResult<HashMap<String, Ipv6Addr>> {
+        let mut nodes = HashMap::new();
+        match self.get_self().await? {
+            Ok(entry) => nodes.insert(entry.key, entry.address),
+            Err(err) => return Ok(Err(err)),
+        };
+        match self.get_sessions().await? {
+            Ok(sessions) => nodes.extend(sessions.into_iter().map(|e| (e.key, e.address))),
+            Err(err) => return Ok(Err(err)),
+        }
+        match self.get_peers().await? {
+            Ok(peers) => nodes.extend(
+                peers
+                    .into_iter()
+                    .filter_map(|e| Some((e.key, e.address?)))
+                    .filter(|(key, _)| !key.is_empty()),
+            ),
+            Err(err) => return Ok(Err(err)),
+        }
+        if let RouterVersion::v0_5_0__ = self.router_version {
+            match self.get_tree().await? {
+                Ok(tree) => nodes.extend(tree.into_iter().map(|e| (e.key, e.address))),
+                Err(err) => return Ok(Err(err)),
+            }
+        }
+        Ok(Ok(nodes))
+    }
+}
+
+impl<S: AsyncWrite + AsyncRead + Unpin> NodeInfoResolver<S> {
+    /// Map `nodes` to names published in their node info, skipping unnamed or unresolved ones.
+    /// Names published by several nodes are suffixed with the start of their keys.
+    pub fn host_entries(&self, nodes: &HashMap<String, Ipv6Addr>) -> Vec<HostEntry> {
+        let mut entries: Vec<HostEntry> = nodes
+            .iter()
+            .filter_map(|(key, address)| {
+                let name = self.get(key)?.as_ref().ok()?.name()?;
+                Some(HostEntry {
+                    name: sanitize_hostname(name)?,
+                    address: *address,
+                    key: key.clone(),
+                })
+            })
+            .collect();
+        disambiguate(&mut entries);
+        entries.sort_by(|a, b| (&a.name, a.address).cmp(&(&b.name, b.address)));
+        entries
+    }
+}
@@ -0,0 +1,820 @@
+use super::*;
+use crate::interface::*;
+use serde::Serialize;
+use serde_json::{value::RawValue, Map};
+use std::net::Ipv6Addr;
+
+impl<S: AsyncWrite + AsyncRead + Unpin> Endpoint<S> {
+    #[maybe_async]
+    pub async fn get_peers(&mut self) -> RequestResult<Vec<PeerEntry>> {
+        let peers = match self.router_version {
+            RouterVersion::v0_3 => {
+                // Unlike ".peers", switch peers include the port and the remote address
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    box_pub_key: String,
+                    bytes_recvd: u64,
+                    bytes_sent: u64,
+                    #[allow(dead_code)]
+                    coords: String,
+                    endpoint: String,
+                    ip: Ipv6Addr,
+                    port: u64,
+                    #[allow(dead_code)]
+                    proto: String,
+                    #[serde(default)]
+                    uptime: Option<f64>,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct SwitchPeers {
+                    switchpeers: HashMap<String, Entry>,
+                }
+                match self.request::<SwitchPeers>("getswitchpeers").await? {
+                    Ok(peers) => {
+                        let mut vec: Vec<_> = peers
+                            .switchpeers
+                            .into_values()
+                            .map(|v| PeerEntry {
+                                address: Some(v.ip),
+                                key: v.box_pub_key,
+                                port: v.port,
+                                remote: Some(v.endpoint),
+                                uptime: v.uptime,
+                                bytes_recvd: Some(v.bytes_recvd),
+                                bytes_sent: Some(v.bytes_sent),
+                                priority: None,
+                                up: true,
+                                inbound: false,
+                                latency: None,
+                                last_error: None,
+                                last_error_time: None,
+                                cost: None,
+                                rate_recvd: None,
+                                rate_sent: None,
+                                extra: v.extra,
+                            })
+                            .collect();
+                        vec.sort_by_key(|p| p.port);
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::__v0_4_4 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    port: u64,
+                    key: String,
+                    #[allow(dead_code)]
+                    coords: Vec<u64>,
+                    remote: String,
+                    bytes_recvd: u64,
+                    bytes_sent: u64,
+                    uptime: f64,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Peers {
+                    peers: HashMap<Ipv6Addr, Entry>,
+                }
+                match self.request::<Peers>("getpeers").await? {
+                    Ok(peers) => {
+                        let vec = peers
+                            .peers
+                            .into_iter()
+                            .map(|(k, v)| PeerEntry {
+                                address: Some(k),
+                                key: v.key,
+                                port: v.port,
+                                remote: Some(v.remote),
+                                uptime: Some(v.uptime),
+                                bytes_recvd: Some(v.bytes_recvd),
+                                bytes_sent: Some(v.bytes_sent),
+                                priority: None,
+                                up: true,
+                                inbound: false,
+                                latency: None,
+                                last_error: None,
+                                last_error_time: None,
+                                cost: None,
+                                rate_recvd: None,
+                                rate_sent: None,
+                                extra: v.extra,
+                            })
+                            .collect();
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::v0_4_5__v0_4_7 => {
+                #[derive(Debug, Serialize, Deserialize)]
+                pub struct Entry {
+                    pub address: Ipv6Addr,
+                    pub key: String,
+                    pub port: u64,
+                    pub priority: u64,
+                    pub coords: Vec<u64>,
+                    pub remote: String,
+                    pub bytes_recvd: u64,
+                    pub bytes_sent: u64,
+                    pub uptime: f64,
+                    #[serde(flatten)]
+                    pub extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Peers {
+                    peers: Vec<Entry>,
+                }
+                match self.request::<Peers>("getpeers").await? {
+                    Ok(peers) => {
+                        let vec = peers
+                            .peers
+                            .into_iter()
+                            .map(|v| PeerEntry {
+                                address: Some(v.address),
+                                key: v.key,
+                                port: v.port,
+                                remote: Some(v.remote),
+                                uptime: Some(v.uptime),
+                                bytes_recvd: Some(v.bytes_recvd),
+                                bytes_sent: Some(v.bytes_sent),
+                                priority: None,
+                                up: true,
+                                inbound: false,
+                                latency: None,
+                                last_error: None,
+                                last_error_time: None,
+                                cost: None,
+                                rate_recvd: None,
+                                rate_sent: None,
+                                extra: v.extra,
+                            })
+                            .collect();
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::v0_5_0__ => {
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Peers {
+                    peers: Vec<PeerEntry>,
+                }
+                self.request::<Peers>("getpeers")
+                    .await
+                    .map(|e| e.map(|e| e.peers))
+            }
+        }?;
+        self.report_unknown_fields("getpeers", peers.iter().flatten().map(|e| &e.extra));
+        Ok(peers)
+    }
+
+    /// Same as `get_peers`, but entries are passed to `on_entry` as they're received.
+    ///
+    /// Only supported by routers since v0.5.0, use `get_peers` for older ones.
+    #[maybe_async]
+    pub async fn stream_peers(&mut self, on_entry: impl FnMut(PeerEntry)) -> RequestResult<()> {
+        if self.router_version != RouterVersion::v0_5_0__ {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Streaming peers is not supported by routers before v0.5.0, use `get_peers`",
+            ));
+        }
+        self.stream("getpeers", "peers", |e: &PeerEntry| &e.extra, on_entry)
+            .await
+    }
+
+    // Unknown fields are reported once the whole response is received
+    #[maybe_async]
+    async fn stream<T: for<'a> Deserialize<'a>>(
+        &mut self,
+        request: &str,
+        field: &str,
+        extra: impl Fn(&T) -> &Map<String, Value>,
+        mut on_entry: impl FnMut(T),
+    ) -> RequestResult<()> {
+        let mut unknown = Map::new();
+        let result = self
+            .request_stream(request, HashMap::new(), field, |entry: T| {
+                unknown.extend(extra(&entry).keys().map(|k| (k.clone(), Value::Null)));
+                on_entry(entry)
+            })
+            .await;
+        self.report_unknown_fields(request, [&unknown]);
+        result
+    }
+    #[maybe_async]
+    pub async fn get_sessions(&mut self) -> RequestResult<Vec<SessionEntry>> {
+        let sessions = match self.router_version {
+            RouterVersion::v0_3 => match self.get_sessions_v0_3().await? {
+                Ok(sessions) => {
+                    let vec = sessions
+                        .into_iter()
+                        .map(|(k, v)| SessionEntry {
+                            address: k,
+                            key: v.box_pub_key,
+                            bytes_recvd: Some(v.bytes_recvd),
+                            bytes_sent: Some(v.bytes_sent),
+                            uptime: Some(v.uptime),
+                            rate_recvd: None,
+                            rate_sent: None,
+                            extra: v.extra,
+                        })
+                        .collect();
+                    Ok(Ok(vec))
+                }
+                Err(err) => Ok(Err(err)),
+            },
+            RouterVersion::__v0_4_4 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    key: String,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Sessions {
+                    sessions: HashMap<Ipv6Addr, Entry>,
+                }
+                match self.request::<Sessions>("getsessions").await? {
+                    Ok(sessions) => {
+                        let vec = sessions
+                            .sessions
+                            .into_iter()
+                            .map(|(k, v)| SessionEntry {
+                                address: k,
+                                key: v.key,
+                                bytes_recvd: None,
+                                bytes_sent: None,
+                                uptime: None,
+                                rate_recvd: None,
+                                rate_sent: None,
+                                extra: v.extra,
+                            })
+                            .collect();
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ => {
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Sessions {
+                    sessions: Vec<SessionEntry>,
+                }
+                self.request::<Sessions>("getsessions")
+                    .await
+                    .map(|e| e.map(|e| e.sessions))
+            }
+        }?;
+        self.report_unknown_fields("getsessions", sessions.iter().flatten().map(|e| &e.extra));
+        Ok(sessions)
+    }
+    #[maybe_async]
+    async fn get_sessions_v0_3(&mut self) -> RequestResult<HashMap<Ipv6Addr, SessionEntryV0_3>> {
+        #[derive(Debug, Deserialize)]
+        #[cfg_attr(test, serde(deny_unknown_fields))]
+        struct Sessions {
+            sessions: HashMap<Ipv6Addr, SessionEntryV0_3>,
+        }
+        self.request::<Sessions>("getsessions")
+            .await
+            .map(|e| e.map(|e| e.sessions))
+    }
+    #[maybe_async]
+    pub async fn add_peer(
+        &mut self,
+        uri: String,
+        interface: Option<String>,
+    ) -> RequestResult<Empty> {
+        let mut args = hash_map! {
+            ("uri".into()): uri.into()
+        };
+        if let Some(interface) = interface {
+            args.insert("interface".into(), interface.into());
+        }
+        self.request_args("addpeer", args).await
+    }
+    #[maybe_async]
+    pub async fn remove_peer(
+        &mut self,
+        uri: String,
+        interface: Option<String>,
+    ) -> RequestResult<Empty> {
+        let mut args = hash_map! {
+            ("uri".into()): uri.into()
+        };
+        if let Some(interface) = interface {
+            args.insert("interface".into(), interface.into());
+        }
+        self.request_args("removepeer", args).await
+    }
+    /// Keys allowed to peer with the node. Empty list means any key is allowed.
+    ///
+    /// Only v0.3 routers manage the list via Admin API, others fail with `ErrorKind::Unsupported`
+    /// and read it from `AllowedPublicKeys` of the config only,
+    /// see [`crate::config::Config::allowed_public_keys`].
+    #[maybe_async]
+    pub async fn get_allowed_public_keys(&mut self) -> RequestResult<Vec<String>> {
+        self.check_allowed_public_keys_support()?;
+        #[derive(Debug, Deserialize)]
+        #[cfg_attr(test, serde(deny_unknown_fields))]
+        struct Allowed {
+            allowed_box_pubs: Vec<String>,
+        }
+        self.request::<Allowed>("getallowedencryptionpublickeys")
+            .await
+            .map(|e| e.map(|e| e.allowed_box_pubs))
+    }
+    /// Returns the list of added keys.
+    #[maybe_async]
+    pub async fn add_allowed_public_key(&mut self, key: String) -> RequestResult<Vec<String>> {
+        self.check_allowed_public_keys_support()?;
+        #[derive(Debug, Deserialize)]
+        #[cfg_attr(test, serde(deny_unknown_fields))]
+        struct Added {
+            added: Vec<String>,
+        }
+        let args = hash_map! {
+            ("box_pub_key".into()): key.into()
+        };
+        self.request_args::<Added>("addallowedencryptionpublickey", args)
+            .await
+            .map(|e| e.map(|e| e.added))
+    }
+    /// Returns the list of removed keys.
+    #[maybe_async]
+    pub async fn remove_allowed_public_key(&mut self, key: String) -> RequestResult<Vec<String>> {
+        self.check_allowed_public_keys_support()?;
+        #[derive(Debug, Deserialize)]
+        #[cfg_attr(test, serde(deny_unknown_fields))]
+        struct Removed {
+            removed: Vec<String>,
+        }
+        let args = hash_map! {
+            ("box_pub_key".into()): key.into()
+        };
+        self.request_args::<Removed>("removeallowedencryptionpublickey", args)
+            .await
+            .map(|e| e.map(|e| e.removed))
+    }
+    fn check_allowed_public_keys_support(&self) -> io::Result<()> {
+        match self.router_version {
+            RouterVersion::v0_3 => Ok(()),
+            // Replaced with `AllowedPublicKeys` of the config, which can't be changed at runtime
+            RouterVersion::__v0_4_4 | RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ => {
+                Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Router doesn't manage allowed public keys via Admin API, \
+                 set `AllowedPublicKeys` in the config and restart the router instead",
+                ))
+            }
+        }
+    }
+    #[maybe_async]
+    pub async fn get_self(&mut self) -> RequestResult<SelfEntry> {
+        let entry = match self.router_version {
+            RouterVersion::v0_3 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    box_pub_key: String,
+                    build_name: String,
+                    build_version: String,
+                    #[allow(dead_code)]
+                    coords: String,
+                    subnet: String,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct _SelfEntry {
+                    #[serde(alias = "self")]
+                    entry: HashMap<Ipv6Addr, Entry>,
+                }
+                match self.request::<_SelfEntry>("getself").await? {
+                    Ok(entry) => match entry.entry.into_iter().next() {
+                        Some((k, v)) => Ok(Ok(SelfEntry {
+                            address: k,
+                            key: v.box_pub_key,
+                            build_name: v.build_name,
+                            build_version: v.build_version,
+                            subnet: v.subnet,
+                            routing_entries: None,
+                            extra: v.extra,
+                        })),
+                        None => Ok(Err("Unknown".to_string())),
+                    },
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::__v0_4_4 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    build_name: String,
+                    build_version: String,
+                    key: String,
+                    #[allow(dead_code)]
+                    coords: Vec<u64>,
+                    subnet: String,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct _SelfEntry {
+                    #[serde(alias = "self")]
+                    entry: HashMap<Ipv6Addr, Entry>,
+                }
+                match self.request::<_SelfEntry>("getself").await? {
+                    Ok(entry) => match entry.entry.into_iter().next() {
+                        Some((k, v)) => Ok(Ok(SelfEntry {
+                            address: k,
+                            key: v.key,
+                            build_name: v.build_name,
+                            build_version: v.build_version,
+                            subnet: v.subnet,
+                            routing_entries: None,
+                            extra: v.extra,
+                        })),
+                        None => Ok(Err("Unknown".to_string())),
+                    },
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::v0_4_5__v0_4_7 => {
+                #[derive(Debug, Serialize, Deserialize)]
+                pub struct Entry {
+                    pub build_name: String,
+                    pub build_version: String,
+                    pub key: String,
+                    pub address: Ipv6Addr,
+                    #[allow(dead_code)]
+                    pub coords: Vec<u64>,
+                    pub subnet: String,
+                    #[serde(flatten)]
+                    pub extra: Map<String, Value>,
+                }
+                match self.request::<Entry>("getself").await? {
+                    Ok(v) => Ok(Ok(SelfEntry {
+                        address: v.address,
+                        key: v.key,
+                        build_name: v.build_name,
+                        build_version: v.build_version,
+                        subnet: v.subnet,
+                        routing_entries: None,
+                        extra: v.extra,
+                    })),
+                    Err(v) => Ok(Err(v)),
+                }
+            }
+            RouterVersion::v0_5_0__ => self.request::<SelfEntry>("getself").await,
+        }?;
+        self.report_unknown_fields("getself", entry.iter().map(|e| &e.extra));
+        Ok(entry)
+    }
+    #[maybe_async]
+    pub async fn get_paths(&mut self) -> RequestResult<Vec<PathEntry>> {
+        let paths = match self.router_version {
+            // Routers before v0.4.0 route by coordinates, reported for each session.
+            // Note that their `getroutes` is crypto-key routing table, not paths.
+            RouterVersion::v0_3 => match self.get_sessions_v0_3().await? {
+                Ok(sessions) => {
+                    let vec = sessions
+                        .into_iter()
+                        .map(|(k, v)| PathEntry {
+                            address: k,
+                            key: v.box_pub_key,
+                            path: parse_coords(&v.coords),
+                            sequence: None,
+                            extra: v.extra,
+                        })
+                        .collect();
+                    Ok(Ok(vec))
+                }
+                Err(err) => Ok(Err(err)),
+            },
+            RouterVersion::__v0_4_4 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    key: String,
+                    path: Vec<u64>,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Paths {
+                    paths: HashMap<Ipv6Addr, Entry>,
+                }
+                match self.request::<Paths>("getpaths").await? {
+                    Ok(paths) => {
+                        let vec = paths
+                            .paths
+                            .into_iter()
+                            .map(|(k, v)| PathEntry {
+                                address: k,
+                                key: v.key,
+                                path: v.path,
+                                sequence: None,
+                                extra: v.extra,
+                            })
+                            .collect();
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ => {
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Paths {
+                    paths: Vec<PathEntry>,
+                }
+                self.request::<Paths>("getpaths")
+                    .await
+                    .map(|e| e.map(|e| e.paths))
+            }
+        }?;
+        self.report_unknown_fields("getpaths", paths.iter().flatten().map(|e| &e.extra));
+        Ok(paths)
+    }
+
+    /// Same as `get_paths`, but entries are passed to `on_entry` as they're received.
+    ///
+    /// Only supported by routers since v0.4.5, use `get_paths` for older ones.
+    #[maybe_async]
+    pub async fn stream_paths(&mut self, on_entry: impl FnMut(PathEntry)) -> RequestResult<()> {
+        if let RouterVersion::v0_3 | RouterVersion::__v0_4_4 = self.router_version {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Streaming paths is not supported by routers before v0.4.5, use `get_paths`",
+            ));
+        }
+        self.stream("getpaths", "paths", |e: &PathEntry| &e.extra, on_entry)
+            .await
+    }
+
+    /// Same as `get_paths`, but entries borrow keys from the response buffer of the endpoint.
+    ///
+    /// Only supported by routers since v0.4.5, use `get_paths` for older ones.
+    #[maybe_async]
+    pub async fn get_paths_ref(&mut self) -> RequestResult<Vec<PathEntryRef<'_>>> {
+        if let RouterVersion::v0_3 | RouterVersion::__v0_4_4 = self.router_version {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Borrowed paths are not supported by routers before v0.4.5, use `get_paths`",
+            ));
+        }
+        #[derive(Debug, Deserialize)]
+        struct Paths<'a> {
+            #[serde(borrow)]
+            paths: Vec<PathEntryRef<'a>>,
+        }
+        Ok(self
+            .request_borrowed::<Paths>("getpaths", HashMap::new())
+            .await?
+            .map(|p| p.paths))
+    }
+
+    /// Same as `get_paths_ref`, but entries are deserialized one at a time as the iterator advances.
+    ///
+    /// Only supported by routers since v0.4.5, use `get_paths` for older ones.
+    #[maybe_async]
+    pub async fn iter_paths(&mut self) -> RequestResult<EntriesRef<'_, PathEntryRef<'_>>> {
+        if let RouterVersion::v0_3 | RouterVersion::__v0_4_4 = self.router_version {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Borrowed paths are not supported by routers before v0.4.5, use `get_paths`",
+            ));
+        }
+        #[derive(Debug, Deserialize)]
+        struct Paths<'a> {
+            #[serde(borrow)]
+            paths: Vec<&'a RawValue>,
+        }
+        Ok(self
+            .request_borrowed::<Paths>("getpaths", HashMap::new())
+            .await?
+            .map(|p| EntriesRef::new(p.paths)))
+    }
+    #[maybe_async]
+    pub async fn get_dht(&mut self) -> RequestResult<Vec<DHTEntry>> {
+        let dht = match self.router_version {
+            // DHT of routers before v0.4.0 is keyed by coordinates, not ports
+            RouterVersion::v0_3 => Err(Error::new(
+                ErrorKind::Unsupported,
+                "DHT of v0.3 routers is not supported",
+            )),
+            RouterVersion::__v0_4_4 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    key: String,
+                    pub port: u64,
+                    pub rest: u64,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Dht {
+                    dht: HashMap<Ipv6Addr, Entry>,
+                }
+                match self.request::<Dht>("getdht").await? {
+                    Ok(dht) => {
+                        let vec = dht
+                            .dht
+                            .into_iter()
+                            .map(|(k, v)| DHTEntry {
+                                address: k,
+                                key: v.key,
+                                port: v.port,
+                                rest: v.rest,
+                                extra: v.extra,
+                            })
+                            .collect();
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            // Not implemented in the router after v0.5.0
+            RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ => {
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct Dht {
+                    dht: Vec<DHTEntry>,
+                }
+                self.request::<Dht>("getdht")
+                    .await
+                    .map(|e| e.map(|e| e.dht))
+            }
+        }?;
+        self.report_unknown_fields("getdht", dht.iter().flatten().map(|e| &e.extra));
+        Ok(dht)
+    }
+    #[maybe_async]
+    pub async fn get_node_info(&mut self, key: &str) -> RequestResult<NodeInfo> {
+        let args = hash_map! {
+            ("key".into()): key.into()
+        };
+        // Response is keyed by the remote key
+        match self
+            .request_args::<HashMap<String, NodeInfo>>("getnodeinfo", args)
+            .await?
+        {
+            Ok(mut info) => match info.remove(key) {
+                Some(info) => Ok(Ok(info)),
+                None => match info.into_values().next() {
+                    Some(info) => Ok(Ok(info)),
+                    None => Ok(Err("Unknown".to_string())),
+                },
+            },
+            Err(err) => Ok(Err(err)),
+        }
+    }
+    #[maybe_async]
+    pub async fn get_multicast_interfaces(&mut self) -> RequestResult<Vec<String>> {
+        #[derive(Debug, Deserialize)]
+        #[cfg_attr(test, serde(deny_unknown_fields))]
+        struct MulticastInterfaces {
+            multicast_interfaces: Vec<String>,
+        }
+        self.request::<MulticastInterfaces>("getmulticastinterfaces")
+            .await
+            .map(|e| e.map(|e| e.multicast_interfaces))
+    }
+    #[maybe_async]
+    pub async fn get_tun(&mut self) -> RequestResult<TunEntry> {
+        let entry = self.request::<TunEntry>("gettun").await?;
+        self.report_unknown_fields("gettun", entry.iter().map(|e| &e.extra));
+        Ok(entry)
+    }
+    #[maybe_async]
+    pub async fn get_tree(&mut self) -> RequestResult<Vec<TreeEntry>> {
+        #[derive(Debug, Deserialize)]
+        #[cfg_attr(test, serde(deny_unknown_fields))]
+        struct Tree {
+            tree: Vec<TreeEntry>,
+        }
+        let tree = self.request::<Tree>("gettree").await?.map(|t| t.tree);
+        self.report_unknown_fields("gettree", tree.iter().flatten().map(|e| &e.extra));
+        Ok(tree)
+    }
+
+    /// Same as `get_tree`, but entries are passed to `on_entry` as they're received,
+    /// which keeps memory use bounded on large networks.
+    ///
+    /// Only supported by routers since v0.5.0.
+    #[maybe_async]
+    pub async fn stream_tree(&mut self, on_entry: impl FnMut(TreeEntry)) -> RequestResult<()> {
+        if self.router_version != RouterVersion::v0_5_0__ {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Tree is not supported by routers before v0.5.0",
+            ));
+        }
+        self.stream("gettree", "tree", |e: &TreeEntry| &e.extra, on_entry)
+            .await
+    }
+
+    /// Same as `get_tree`, but entries borrow keys from the response buffer of the endpoint.
+    ///
+    /// Only supported by routers since v0.5.0.
+    #[maybe_async]
+    pub async fn get_tree_ref(&mut self) -> RequestResult<Vec<TreeEntryRef<'_>>> {
+        if self.router_version != RouterVersion::v0_5_0__ {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Tree is not supported by routers before v0.5.0",
+            ));
+        }
+        #[derive(Debug, Deserialize)]
+        struct Tree<'a> {
+            #[serde(borrow)]
+            tree: Vec<TreeEntryRef<'a>>,
+        }
+        Ok(self
+            .request_borrowed::<Tree>("gettree", HashMap::new())
+            .await?
+            .map(|t| t.tree))
+    }
+
+    /// Same as `get_tree_ref`, but entries are deserialized one at a time as the iterator advances.
+    ///
+    /// Only supported by routers since v0.5.0.
+    #[maybe_async]
+    pub async fn iter_tree(&mut self) -> RequestResult<EntriesRef<'_, TreeEntryRef<'_>>> {
+        if self.router_version != RouterVersion::v0_5_0__ {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Tree is not supported by routers before v0.5.0",
+            ));
+        }
+        #[derive(Debug, Deserialize)]
+        struct Tree<'a> {
+            #[serde(borrow)]
+            tree: Vec<&'a RawValue>,
+        }
+        Ok(self
+            .request_borrowed::<Tree>("gettree", HashMap::new())
+            .await?
+            .map(|t| EntriesRef::new(t.tree)))
+    }
+    #[maybe_async]
+    pub async fn list(&mut self) -> RequestResult<Vec<ListEntry>> {
+        let list = match self.router_version {
+            RouterVersion::v0_3 | RouterVersion::__v0_4_4 => {
+                #[derive(Debug, Deserialize)]
+                struct Entry {
+                    fields: Vec<String>,
+                    #[serde(flatten)]
+                    extra: Map<String, Value>,
+                }
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct List {
+                    list: HashMap<String, Entry>,
+                }
+                match self.request::<List>("list").await? {
+                    Ok(list) => {
+                        let vec = list
+                            .list
+                            .into_iter()
+                            .map(|(k, v)| ListEntry {
+                                command: k,
+                                description: String::new(),
+                                fields: Some(v.fields),
+                                extra: v.extra,
+                            })
+                            .collect();
+                        Ok(Ok(vec))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            }
+            RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ => {
+                #[derive(Debug, Deserialize)]
+                #[cfg_attr(test, serde(deny_unknown_fields))]
+                struct List {
+                    list: Vec<ListEntry>,
+                }
+                self.request::<List>("list")
+                    .await
+                    .map(|e| e.map(|e| e.list))
+            }
+        }?;
+        self.report_unknown_fields("list", list.iter().flatten().map(|e| &e.extra));
+        Ok(list)
+    }
+}
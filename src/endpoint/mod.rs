@@ -0,0 +1,1260 @@
+//! Source shared by `blocking` and `r#async` modules, compiled once for each of them.
+//!
+//! Items are resolved through the parent module, which sets `maybe_async`
+//! to either `must_be_sync` or `must_be_async`, and provides the I/O traits.
+
+use super::*;
+use crate::{
+    redact_request, redact_response, EndpointOptions, Observer, Outcome, RawResponse, RequestEvent,
+    RequestMode, RequestResult, RouterVersion, SocketState,
+};
+use {
+    serde::Deserialize,
+    serde_json::Value,
+    std::{
+        collections::{HashMap, HashSet},
+        io,
+        io::Error,
+        io::ErrorKind,
+        time::Instant,
+    },
+};
+
+mod config;
+mod diff;
+mod hosts;
+mod interface;
+mod resolver;
+mod server;
+pub use resolver::NodeInfoResolver;
+if_blocking! {
+    pub(crate) use server::{serve_limited, serve_with};
+}
+pub use server::Server;
+
+// For benchmarks and fuzzing, not part of the public API
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use protocol::read_response;
+
+type SchemaDriftHook = Box<dyn FnMut(&str, &str) + Send>;
+
+pub struct Endpoint<S> {
+    scratch: Vec<u8>,
+    socket: S,
+    router_version: RouterVersion,
+    request_mode: RequestMode,
+    socket_state: SocketState,
+    options: EndpointOptions,
+    observer: Option<Box<dyn Observer>>,
+    schema_drift_hook: Option<SchemaDriftHook>,
+    // Unknown fields already reported, as `(request, field)`
+    schema_drift_seen: HashSet<(String, String)>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for Endpoint<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("socket", &self.socket)
+            .field("router_version", &self.router_version)
+            .field("request_mode", &self.request_mode)
+            .field("socket_state", &self.socket_state)
+            .field("options", &self.options)
+            .field("observer", &self.observer.is_some())
+            .field("schema_drift_hook", &self.schema_drift_hook.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: AsyncWrite + AsyncRead + Unpin> Endpoint<S> {
+    #[maybe_async]
+    pub async fn attach(socket: S) -> Self {
+        Self::attach_with_options(socket, EndpointOptions::default()).await
+    }
+
+    /// Same as `attach`, but with custom buffering of responses.
+    #[maybe_async]
+    pub async fn attach_with_options(socket: S, options: EndpointOptions) -> Self {
+        // Assume router is of last known version
+        let mut endpoint =
+            Self::attach_version_with_options(socket, RouterVersion::v0_5_0__, options);
+
+        if let Ok(Ok(val)) = endpoint.request::<Value>("getself").await {
+            // Routers before v0.4.5 expose ".self.<addr>.build_version"
+            if let Some(entry) = val.get("self") {
+                // Routers before v0.4.0 identify nodes by ".box_pub_key" instead of ".key"
+                let entry = entry.as_object().and_then(|e| e.values().next());
+                endpoint.router_version = match entry.and_then(|e| e.get("box_pub_key")) {
+                    Some(_) => RouterVersion::v0_3,
+                    None => RouterVersion::__v0_4_4,
+                };
+                return endpoint;
+            }
+
+            // Routers from v0.4.5 expose ".build_version"
+            if let Some(v) = val.get("build_version") {
+                if let Some(v) = v.as_str() {
+                    let v: Vec<i32> = v
+                        .split(['.', '-'].as_slice())
+                        .take(3)
+                        .filter_map(|i| str::parse(i).ok())
+                        .collect();
+                    if v.len() == 3 && v[0] == 0 && v[1] == 4 {
+                        endpoint.router_version = RouterVersion::v0_4_5__v0_4_7;
+                        return endpoint;
+                    }
+                }
+            }
+        }
+
+        endpoint
+    }
+
+    pub fn attach_version(socket: S, router_version: RouterVersion) -> Self {
+        Self::attach_version_with_options(socket, router_version, EndpointOptions::default())
+    }
+
+    /// Same as `attach_version`, but with custom buffering of responses.
+    pub fn attach_version_with_options(
+        socket: S,
+        router_version: RouterVersion,
+        options: EndpointOptions,
+    ) -> Self {
+        Self {
+            scratch: Vec::new(),
+            socket,
+            router_version,
+            request_mode: RequestMode::KeepAlive,
+            socket_state: SocketState::Open,
+            options,
+            observer: None,
+            schema_drift_hook: None,
+            schema_drift_seen: HashSet::new(),
+        }
+    }
+
+    /// Set an observer notified of every request made through the endpoint, e.g. for auditing.
+    pub fn set_observer(&mut self, observer: impl Observer + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Set a function called as `hook(request, field)` when a response entry contains
+    /// a field unknown to this version of the crate, once per field of each request.
+    /// Such fields are kept in `extra` of the entries regardless of the hook.
+    pub fn set_schema_drift_hook(&mut self, hook: impl FnMut(&str, &str) + Send + 'static) {
+        self.schema_drift_hook = Some(Box::new(hook));
+    }
+
+    pub(crate) fn report_unknown_fields<'a>(
+        &mut self,
+        request: &str,
+        extras: impl IntoIterator<Item = &'a serde_json::Map<String, Value>>,
+    ) {
+        let Some(hook) = &mut self.schema_drift_hook else {
+            return;
+        };
+        for field in extras.into_iter().flat_map(|extra| extra.keys()) {
+            if self
+                .schema_drift_seen
+                .insert((request.to_string(), field.clone()))
+            {
+                hook(request, field);
+            }
+        }
+    }
+
+    pub fn get_version(&self) -> RouterVersion {
+        self.router_version.clone()
+    }
+
+    /// Mode used by `request`, `request_args` and all the wrappers.
+    ///
+    /// Note that `attach` probes the router before the mode can be set,
+    /// use `attach_version` for endpoints accepting a single request per connection.
+    /// Once a one-shot request is made, the socket is closed until `replace_socket` is called.
+    pub fn set_request_mode(&mut self, mode: RequestMode) {
+        self.request_mode = mode;
+    }
+
+    /// Use `mode` for requests made through the returned guard only,
+    /// e.g. `e.with_request_mode(RequestMode::OneShot).get_self()`.
+    pub fn with_request_mode(&mut self, mode: RequestMode) -> RequestModeGuard<'_, S> {
+        let previous = std::mem::replace(&mut self.request_mode, mode);
+        RequestModeGuard {
+            endpoint: self,
+            previous,
+        }
+    }
+
+    pub fn get_request_mode(&self) -> RequestMode {
+        self.request_mode
+    }
+
+    pub fn get_socket_state(&self) -> SocketState {
+        self.socket_state
+    }
+
+    /// Fail requests with responses larger than `max` bytes, instead of buffering them.
+    /// Same as setting `EndpointOptions::max_response_size`.
+    pub fn set_max_response_size(&mut self, max: Option<usize>) {
+        self.options.max_response_size = max;
+    }
+
+    pub fn get_max_response_size(&self) -> Option<usize> {
+        self.options.max_response_size
+    }
+
+    /// Takes effect from the next request.
+    pub fn set_options(&mut self, options: EndpointOptions) {
+        self.options = options;
+    }
+
+    pub fn get_options(&self) -> &EndpointOptions {
+        &self.options
+    }
+
+    pub fn into_inner(self) -> S {
+        self.socket
+    }
+
+    /// Continue on a new connection to the same router, e.g. after a one-shot request,
+    /// keeping the version, options and hooks of the endpoint. Returns the previous socket.
+    pub fn replace_socket(&mut self, socket: S) -> S {
+        self.socket_state = SocketState::Open;
+        std::mem::replace(&mut self.socket, socket)
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.socket
+    }
+
+    #[maybe_async]
+    pub async fn request<T: for<'a> Deserialize<'a>>(&mut self, request: &str) -> RequestResult<T> {
+        self.request_args::<T>(request, hash_map!()).await
+    }
+
+    #[maybe_async]
+    pub async fn request_args<T: for<'a> Deserialize<'a>>(
+        &mut self,
+        request: &str,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> RequestResult<T> {
+        self.request_args_with_mode(request, arguments, self.request_mode)
+            .await
+    }
+
+    /// Same as `request_args`, but overrides the mode set for the endpoint.
+    #[maybe_async]
+    pub async fn request_args_with_mode<T: for<'a> Deserialize<'a>>(
+        &mut self,
+        request: &str,
+        arguments: HashMap<String, serde_json::Value>,
+        mode: RequestMode,
+    ) -> RequestResult<T> {
+        let request = protocol::Request {
+            request: request.into(),
+            arguments,
+            keepalive: mode == RequestMode::KeepAlive,
+        };
+        let response = match self.exchange(&request, mode).await {
+            Ok(buf) => parse_response(&request.request, buf),
+            Err(err) => Err(err),
+        };
+        self.shrink_scratch();
+        response
+    }
+
+    /// Same as `request_args`, but `T` may borrow from the response buffer, e.g. with `&str` fields,
+    /// which saves allocations on large responses. The endpoint is lent to `T` until it's dropped.
+    #[maybe_async]
+    pub async fn request_borrowed<'a, T: Deserialize<'a>>(
+        &'a mut self,
+        request: &str,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> RequestResult<T> {
+        let mode = self.request_mode;
+        let request = protocol::Request {
+            request: request.into(),
+            arguments,
+            keepalive: mode == RequestMode::KeepAlive,
+        };
+        let buf = self.exchange(&request, mode).await?;
+        parse_response(&request.request, buf)
+    }
+
+    /// Send a request and return the response as is, including the request echoed by the router.
+    ///
+    /// Only I/O errors and responses that are not JSON objects are reported as `Err`.
+    #[maybe_async]
+    pub async fn request_raw(
+        &mut self,
+        request: &str,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> io::Result<RawResponse> {
+        let request = protocol::Request {
+            request: request.into(),
+            arguments,
+            keepalive: self.request_mode == RequestMode::KeepAlive,
+        };
+        let response = match self.exchange(&request, self.request_mode).await {
+            Ok(buf) => parse_raw_response(&request.request, buf),
+            Err(err) => Err(err),
+        };
+        self.shrink_scratch();
+        response
+    }
+
+    /// Send a request and pass elements of `.response.<field>` array to `on_element` as they arrive,
+    /// so that only a single element is buffered at a time, rather than the whole response.
+    ///
+    /// Observers receive no response bytes for such requests.
+    #[maybe_async]
+    pub async fn request_stream<T: for<'a> Deserialize<'a>>(
+        &mut self,
+        request: &str,
+        arguments: HashMap<String, serde_json::Value>,
+        field: &str,
+        mut on_element: impl FnMut(T),
+    ) -> RequestResult<()> {
+        let mode = self.request_mode;
+        let request = protocol::Request {
+            request: request.into(),
+            arguments,
+            keepalive: mode == RequestMode::KeepAlive,
+        };
+        self.check_open(mode)?;
+        let serialized = serde_json::to_vec(&request)?;
+        let started = Instant::now();
+        let result = match self.socket.write_all(&serialized).await {
+            Ok(()) => {
+                protocol::read_stream(&mut self.socket, field, &self.options, |element| {
+                    let element = serde_json::from_slice(element).map_err(|err| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "While parsing {field:?} element of endpoint response for request {:?}: {err}",
+                                request.request
+                            ),
+                        )
+                    })?;
+                    on_element(element);
+                    Ok(())
+                })
+                .await
+            }
+            Err(err) => Err(err),
+        };
+
+        if result.is_err() {
+            // Rest of the response is left unread
+            self.socket_state = SocketState::Closed;
+        }
+        let outcome = match &result {
+            Ok(Ok(())) => Outcome::Success,
+            Ok(Err(err)) => Outcome::RouterError(err.as_str().into()),
+            Err(err) => Outcome::Io(err),
+        };
+        notify(&mut self.observer, &request, started, None, outcome)?;
+        result
+    }
+
+    #[maybe_async]
+    async fn exchange(
+        &mut self,
+        request: &protocol::Request<'_>,
+        mode: RequestMode,
+    ) -> io::Result<&[u8]> {
+        self.check_open(mode)?;
+        // Buffer may still be large after a borrowed response
+        self.shrink_scratch();
+        let serialized = serde_json::to_vec(request)?;
+        let started = Instant::now();
+        let options = &self.options;
+        let response = match self.socket.write_all(&serialized).await {
+            Ok(()) => match mode {
+                RequestMode::KeepAlive => {
+                    protocol::read_response(&mut self.socket, &mut self.scratch, options).await
+                }
+                RequestMode::OneShot => {
+                    protocol::read_to_end(&mut self.socket, &mut self.scratch, options).await
+                }
+            },
+            Err(err) => Err(err),
+        };
+
+        if response.is_err() {
+            // Rest of the response is left unread
+            self.socket_state = SocketState::Closed;
+        }
+        let outcome = Outcome::of_response(&response);
+        notify(
+            &mut self.observer,
+            request,
+            started,
+            response.as_ref().ok().copied(),
+            outcome,
+        )?;
+        response
+    }
+
+    fn shrink_scratch(&mut self) {
+        let size = self.options.initial_buffer_size;
+        if self.options.shrink_after_use && self.scratch.capacity() > size {
+            self.scratch.truncate(size);
+            self.scratch.shrink_to(size);
+        }
+    }
+
+    fn check_open(&mut self, mode: RequestMode) -> io::Result<()> {
+        if self.socket_state == SocketState::Closed {
+            return Err(Error::new(
+                ErrorKind::NotConnected,
+                "Socket was closed after a one-shot or failed request, see `Endpoint::replace_socket`",
+            ));
+        }
+        if mode == RequestMode::OneShot {
+            // Not reusable even if the request fails
+            self.socket_state = SocketState::Closed;
+        }
+        Ok(())
+    }
+}
+
+/// Endpoint using another request mode until dropped, see `Endpoint::with_request_mode`.
+pub struct RequestModeGuard<'a, S> {
+    endpoint: &'a mut Endpoint<S>,
+    previous: RequestMode,
+}
+
+impl<S> std::ops::Deref for RequestModeGuard<'_, S> {
+    type Target = Endpoint<S>;
+    fn deref(&self) -> &Endpoint<S> {
+        self.endpoint
+    }
+}
+
+impl<S> std::ops::DerefMut for RequestModeGuard<'_, S> {
+    fn deref_mut(&mut self) -> &mut Endpoint<S> {
+        self.endpoint
+    }
+}
+
+impl<S> Drop for RequestModeGuard<'_, S> {
+    fn drop(&mut self) {
+        self.endpoint.request_mode = self.previous;
+    }
+}
+
+fn notify(
+    observer: &mut Option<Box<dyn Observer>>,
+    request: &protocol::Request<'_>,
+    started: Instant,
+    response: Option<&[u8]>,
+    outcome: Outcome<'_>,
+) -> io::Result<()> {
+    let Some(observer) = observer else {
+        return Ok(());
+    };
+    let latency = started.elapsed();
+    let mut redacted = serde_json::to_value(request)?;
+    redact_request(&mut redacted);
+    // Responses echo the request, and may carry URIs with credentials
+    let response = match response.map(serde_json::from_slice::<Value>) {
+        Some(Ok(mut response)) => {
+            redact_response(&mut response);
+            Some(serde_json::to_vec(&response)?)
+        }
+        _ => None,
+    };
+    observer.observe(&RequestEvent {
+        request: &request.request,
+        serialized_request: &serde_json::to_vec(&redacted)?,
+        response: response.as_deref(),
+        latency,
+        outcome,
+    });
+    Ok(())
+}
+
+fn parse_raw_response(request: &str, buf: &[u8]) -> io::Result<RawResponse> {
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        status: String,
+        error: Option<String>,
+        #[serde(default)]
+        request: Value,
+        #[serde(default)]
+        response: Value,
+    }
+    let response: Response = serde_json::from_slice(buf).map_err(|err| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "While parsing endpoint response for request {request:?}: {err}, response: {:?}",
+                String::from_utf8_lossy(buf)
+            ),
+        )
+    })?;
+    Ok(RawResponse {
+        status: response.status,
+        error: response.error,
+        request: response.request,
+        response: response.response,
+        bytes: buf.to_vec(),
+    })
+}
+
+fn parse_response<'a, T: Deserialize<'a>>(request: &str, buf: &'a [u8]) -> RequestResult<T> {
+    let response: protocol::Response<T> = serde_json::from_slice(buf).map_err(|err| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("While parsing endpoint response for request {request:?}: {err}"),
+        )
+    })?;
+    Ok(match (response.status.as_str(), response.response) {
+        ("success", Some(response)) => Ok(response),
+        _ => Err(response.error.unwrap_or_else(|| "Unknown".to_string())),
+    })
+}
+
+mod protocol {
+    use super::*;
+
+    pub use crate::protocol::{Request, Response};
+    use crate::{stream::ArrayScanner, ResponseTooLarge};
+
+    fn too_large(limit: usize) -> Error {
+        Error::new(ErrorKind::InvalidData, ResponseTooLarge { limit })
+    }
+
+    /// Currently there's no well known json deserializer supporting async,
+    /// so for the time being response separation is done using heuristics.
+    ///
+    /// This function assumes:
+    ///   - response is strictly formatted (pretty or compact format).
+    ///   - `reader` doesn't yield anything besides a single json object.
+    #[maybe_async]
+    pub async fn read_response<'a, R: AsyncRead + Unpin>(
+        reader: &mut R,
+        scratch: &'a mut Vec<u8>,
+        options: &EndpointOptions,
+    ) -> io::Result<&'a [u8]> {
+        let max_size = options.max_response_size;
+        if scratch.is_empty() {
+            scratch.extend(std::iter::repeat_n(0, options.initial_buffer_size.max(1)));
+        }
+        let mut len = 0;
+        loop {
+            if len == scratch.len() {
+                // Double the scratch buffer capacity, but don't go much past the limit
+                let cap = max_size.map_or(len * 2, |max| (len * 2).min(max + 1).max(len + 1));
+                scratch.resize(cap, 0);
+            }
+            let cap = scratch.len();
+            let read = reader.read(&mut scratch[len..cap]).await?;
+            if read == 0 {
+                // EOF
+                break;
+            }
+            len += read;
+            if let Some(max) = max_size.filter(|max| len > *max) {
+                return Err(too_large(max));
+            }
+            if len <= 2 {
+                continue;
+            }
+            let buf = &scratch[0..len];
+            if buf.starts_with(b"{\n") {
+                // Pretty
+                if buf.ends_with(b"\n}\n") {
+                    break;
+                }
+            } else {
+                // Compact
+                if buf.ends_with(b"}\n") {
+                    break;
+                }
+            }
+        }
+        Ok(&scratch[0..len])
+    }
+
+    /// Read until the other side closes the connection.
+    #[maybe_async]
+    pub async fn read_to_end<'a, R: AsyncRead + Unpin>(
+        reader: &mut R,
+        scratch: &'a mut Vec<u8>,
+        options: &EndpointOptions,
+    ) -> io::Result<&'a [u8]> {
+        scratch.clear();
+        match options.max_response_size {
+            Some(max) => {
+                // One byte past the limit tells whether it's exceeded
+                (&mut *reader)
+                    .take(max as u64 + 1)
+                    .read_to_end(scratch)
+                    .await?;
+                if scratch.len() > max {
+                    return Err(too_large(max));
+                }
+            }
+            None => {
+                reader.read_to_end(scratch).await?;
+            }
+        }
+        Ok(scratch)
+    }
+
+    /// Pass elements of `.response.<field>` array to `on_element` as they arrive.
+    #[maybe_async]
+    pub async fn read_stream<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        field: &str,
+        options: &EndpointOptions,
+        mut on_element: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> RequestResult<()> {
+        let max_size = options.max_response_size;
+        let mut scanner = ArrayScanner::new(field);
+        let mut chunk = vec![0; options.initial_buffer_size.max(1)];
+        while !scanner.is_complete() {
+            let read = reader.read(&mut chunk).await?;
+            if read == 0 {
+                // EOF
+                break;
+            }
+            if let Some(max) = max_size.filter(|max| scanner.total() + read > *max) {
+                return Err(too_large(max));
+            }
+            scanner.feed(&chunk[..read], &mut on_element)?;
+        }
+        scanner.finish()
+    }
+}
+
+if_blocking! {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeEntry;
+
+    use std::{
+        borrow::Cow,
+        io::{Cursor, Read, Write},
+    };
+
+    macro_rules! mock_reader {
+        ($($read_count:expr => $slice:expr $(,)?)*) => {{
+            struct MockReader {
+                read_counter: u32,
+            }
+            impl Write for MockReader {
+                fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                    Ok(buf.len())
+                }
+                fn flush(&mut self) -> io::Result<()> {
+                    Ok(())
+                }
+            }
+            impl Read for MockReader {
+                fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                    self.read_counter += 1;
+                    match self.read_counter {
+                        $($read_count => {
+                            return Cursor::new(buf).write($slice);
+                        }),*
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            impl Drop for MockReader {
+                fn drop(&mut self) {
+                    let read_expected = 0 $(.max($read_count))*;
+                    if self.read_counter != read_expected {
+                        panic!("Mocked socket seen {} reads, while expected {}", self.read_counter, read_expected)
+                    }
+                }
+            }
+            MockReader { read_counter: 0 }
+        }};
+    }
+
+    // Serialized successful response of the router
+    fn success(response: Value) -> Vec<u8> {
+        let json = serde_json::json!({ "status": "success", "response": response });
+        let mut vec = serde_json::to_vec_pretty(&json).unwrap();
+        vec.push(b'\n');
+        vec
+    }
+
+    #[test]
+    fn simple() {
+        let sock = mock_reader!(
+            1 => &{
+                let json = serde_json::json!({
+                    "status": "success",
+                    "request": {
+                        "request": "test",
+                        "arguments": {},
+                    },
+                    "response": {
+                        "mock": 42,
+                    }
+                });
+                let mut vec = serde_json::to_vec_pretty(&json).unwrap();
+                vec.push(b'\n');
+                vec
+            }
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        #[derive(Debug, Deserialize, PartialEq, Eq)]
+        struct MockResult {
+            mock: u32,
+        }
+        let res: MockResult = Endpoint::request(&mut e, "test").unwrap().unwrap();
+        assert_eq!(res, MockResult { mock: 42 });
+    }
+
+    #[test]
+    fn raw() {
+        let sock = mock_reader!(
+            1 => &{
+                let json = serde_json::json!({
+                    "status": "error",
+                    "error": "mock",
+                    "request": {
+                        "request": "getmock",
+                        "arguments": { "key": "abcd" },
+                        "keepalive": true,
+                    },
+                });
+                let mut vec = serde_json::to_vec_pretty(&json).unwrap();
+                vec.push(b'\n');
+                vec
+            }
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        let args = hash_map! { ("key".to_string()): "abcd".into() };
+        let raw = e.request_raw("getMock", args.clone()).unwrap();
+        assert!(!raw.is_success());
+        assert_eq!(raw.error.as_deref(), Some("mock"));
+        assert_eq!(raw.response, Value::Null);
+        assert!(raw.bytes.ends_with(b"\n}\n"));
+        assert!(raw.is_echo_of("getMock", &args));
+        assert!(!raw.is_echo_of("getMock", &HashMap::new()));
+        assert!(!raw.is_echo_of("getself", &args));
+    }
+
+    #[test]
+    fn observer() {
+        let sock = mock_reader!(
+            1 => &{
+                let json = serde_json::json!({
+                    "status": "error",
+                    "error": "mock",
+                    "request": {
+                        "request": "addpeer",
+                        "arguments": { "uri": "tls://a:1?password=secret" },
+                    },
+                });
+                let mut vec = serde_json::to_vec(&json).unwrap();
+                vec.push(b'\n');
+                vec
+            }
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        e.set_observer({
+            let seen = seen.clone();
+            move |event: &RequestEvent| {
+                let request = String::from_utf8(event.serialized_request.to_vec()).unwrap();
+                let response = String::from_utf8(event.response.unwrap().to_vec()).unwrap();
+                let outcome = format!("{:?}", event.outcome);
+                seen.lock()
+                    .unwrap()
+                    .push((event.request.to_string(), request, response, outcome));
+            }
+        });
+        let uri = "tls://a:1?password=secret".to_string();
+        assert_eq!(e.add_peer(uri, None).unwrap(), Err("mock".to_string()));
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].0, "addpeer");
+        assert!(seen[0].1.contains("password=<redacted>"));
+        assert!(!seen[0].1.contains("secret"));
+        assert!(seen[0].2.contains("password=<redacted>"));
+        assert!(!seen[0].2.contains("secret"));
+        assert_eq!(seen[0].3, r#"RouterError("mock")"#);
+    }
+
+    #[test]
+    fn node_info() {
+        let sock = mock_reader!(
+            1 => &{
+                let json = serde_json::json!({
+                    "status": "success",
+                    "response": {
+                        "abcd": {
+                            "buildname": "yggdrasil",
+                            "buildversion": "0.5.12",
+                            "name": "mock",
+                        }
+                    }
+                });
+                let mut vec = serde_json::to_vec(&json).unwrap();
+                vec.push(b'\n');
+                vec
+            }
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        let info = e.get_node_info("abcd").unwrap().unwrap();
+        assert_eq!(info.buildversion.as_deref(), Some("0.5.12"));
+        assert_eq!(info.buildarch, None);
+        assert_eq!(info.name(), Some("mock"));
+        assert_eq!(info.contact(), None);
+    }
+
+    #[test]
+    fn borrowed() {
+        fn tree() -> Vec<u8> {
+            success(serde_json::json!({ "tree": [
+                { "address": "200::1", "key": "aa", "parent": "aa", "sequence": 1, "new": 0 },
+                { "address": "200::2", "key": "bb", "parent": "a\"", "sequence": 2 },
+            ]}))
+        }
+        fn paths() -> Vec<u8> {
+            success(serde_json::json!({ "paths": [
+                { "address": "200::2", "key": "bb", "path": [1, 2], "sequence": 3 },
+            ]}))
+        }
+        let sock = mock_reader!(
+            1 => &tree(),
+            2 => &tree(),
+            3 => &paths(),
+            4 => &tree(),
+            5 => &paths(),
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        let owned = e.get_tree().unwrap().unwrap();
+        let tree = e.get_tree_ref().unwrap().unwrap();
+        assert!(matches!(tree[1].key, Cow::Borrowed("bb")));
+        // Escaped strings can't be borrowed
+        assert!(matches!(tree[1].parent, Cow::Owned(_)));
+        assert_eq!(tree[1].parent, "a\"");
+        // Unknown fields are skipped by borrowed entries
+        assert_eq!(TreeEntry::from(tree[0].clone()).extra.len(), 0);
+        assert_eq!(TreeEntry::from(tree[1].clone()), owned[1]);
+        let paths = e.get_paths_ref().unwrap().unwrap();
+        assert_eq!(paths[0].key, "bb");
+        assert_eq!(paths[0].path, [1, 2]);
+        assert_eq!(paths[0].sequence, Some(3));
+
+        let mut entries = e.iter_tree().unwrap().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries.next().unwrap().unwrap().key, "aa");
+        let entry = TreeEntry::from(entries.next().unwrap().unwrap());
+        assert_eq!(entry, owned[1]);
+        assert!(entries.next().is_none());
+        let paths: Vec<_> = e.iter_paths().unwrap().unwrap().collect();
+        assert_eq!(paths[0].as_ref().unwrap().key, "bb");
+
+        let mut e = Endpoint::attach_version(Cursor::new(Vec::new()), RouterVersion::__v0_4_4);
+        let err = e.get_paths_ref().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let err = e.iter_paths().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let err = e.get_tree_ref().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let err = e.iter_tree().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(e.socket.get_ref().is_empty());
+    }
+
+    #[test]
+    fn stream() {
+        fn tree() -> Vec<u8> {
+            success(serde_json::json!({ "tree": [
+                { "address": "200::1", "key": "aa", "parent": "aa", "sequence": 1, "new": 0 },
+                { "address": "200::2", "key": "bb", "parent": "aa", "sequence": 2 },
+            ]}))
+        }
+        fn head() -> Vec<u8> {
+            tree()[..60].to_vec()
+        }
+        fn tail() -> Vec<u8> {
+            tree()[60..].to_vec()
+        }
+        let sock = mock_reader!(1 => &head(), 2 => &tail(), 3 => &tree());
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        let unknown = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = unknown.clone();
+        e.set_schema_drift_hook(move |_, field| reported.lock().unwrap().push(field.to_string()));
+        let mut entries = Vec::new();
+        e.stream_tree(|entry| entries.push(entry)).unwrap().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].key, "bb");
+        assert_eq!(*unknown.lock().unwrap(), ["new"]);
+
+        // Second response is a single read
+        e.set_max_response_size(Some(100));
+        let err = e.stream_tree(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(e.get_socket_state(), SocketState::Closed);
+
+        let mut e = Endpoint::attach_version(Cursor::new(Vec::new()), RouterVersion::v0_4_5__v0_4_7);
+        let err = e.stream_tree(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(e.socket.get_ref().is_empty());
+    }
+
+    #[test]
+    fn max_response_size() {
+        let response = success(serde_json::json!({ "data": "a".repeat(100) }));
+        let sock = Replay(Cursor::new(response));
+        let options = EndpointOptions {
+            initial_buffer_size: 16,
+            max_response_size: Some(32),
+            shrink_after_use: true,
+        };
+        let mut e = Endpoint::attach_version_with_options(sock, RouterVersion::v0_5_0__, options);
+        let err = e.request::<Value>("getself").unwrap_err();
+        assert!(e.scratch.capacity() <= 16);
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            crate::ResponseTooLarge::find(&err),
+            Some(&crate::ResponseTooLarge { limit: 32 })
+        );
+        // Remaining bytes of the response would be read as the next one
+        assert_eq!(e.get_socket_state(), SocketState::Closed);
+        let err = e.request::<Value>("getself").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotConnected);
+    }
+
+    // Socket replaying the given bytes, ignoring requests
+    struct Replay(Cursor<Vec<u8>>);
+    impl Read for Replay {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+    impl Write for Replay {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shrink_after_use() {
+        for shrink_after_use in [false, true] {
+            let large = success(serde_json::json!({ "data": "a".repeat(1000) }));
+            let sock = Replay(Cursor::new(large));
+            let options = EndpointOptions {
+                initial_buffer_size: 64,
+                shrink_after_use,
+                ..Default::default()
+            };
+            let mut e = Endpoint::attach_version_with_options(sock, RouterVersion::v0_5_0__, options);
+            e.request::<Value>("getlarge").unwrap().unwrap();
+            assert_eq!(e.scratch.capacity() <= 64, shrink_after_use);
+        }
+    }
+
+    #[test]
+    fn one_shot() {
+        let sock = mock_reader!(
+            1 => br#"{"status":"success","response":{"#,
+            2 => br#""enabled":false}}"#,
+            3 => b"",
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        e.set_request_mode(RequestMode::OneShot);
+        assert!(!e.get_tun().unwrap().unwrap().enabled);
+        assert_eq!(e.get_socket_state(), SocketState::Closed);
+        let err = e.get_tun().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn one_shot_per_call() {
+        let response = success(serde_json::json!({ "enabled": false }));
+        let sock = Replay(Cursor::new(response.clone()));
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        {
+            let mut one_shot = e.with_request_mode(RequestMode::OneShot);
+            assert_eq!(one_shot.get_request_mode(), RequestMode::OneShot);
+            assert!(!one_shot.get_tun().unwrap().unwrap().enabled);
+        }
+        assert_eq!(e.get_request_mode(), RequestMode::KeepAlive);
+        assert_eq!(e.get_socket_state(), SocketState::Closed);
+
+        e.replace_socket(Replay(Cursor::new(response)));
+        assert_eq!(e.get_socket_state(), SocketState::Open);
+        assert!(!e.get_tun().unwrap().unwrap().enabled);
+        assert_eq!(e.get_socket_state(), SocketState::Open);
+    }
+
+    #[test]
+    fn schema_drift() {
+        fn response() -> Vec<u8> {
+            success(serde_json::json!({
+                "enabled": true,
+                "name": "tun0",
+                "mtu": 65535,
+                "queues": 2,
+            }))
+        }
+        let sock = mock_reader!(1 => &response(), 2 => &response());
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_5_0__);
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        e.set_schema_drift_hook({
+            let seen = seen.clone();
+            move |request, field| seen.lock().unwrap().push(format!("{request}.{field}"))
+        });
+        let tun = e.get_tun().unwrap().unwrap();
+        assert_eq!(tun.extra["queues"], 2);
+        e.get_tun().unwrap().unwrap();
+        assert_eq!(*seen.lock().unwrap(), ["gettun.queues"]);
+    }
+
+    #[test]
+    fn schema_drift_legacy() {
+        let sock = mock_reader!(1 => &success(serde_json::json!({
+            "peers": {
+                "200:1234::1": {
+                    "port": 1,
+                    "key": "abcd",
+                    "coords": [1, 2],
+                    "remote": "tls://192.0.2.1:443",
+                    "bytes_recvd": 1,
+                    "bytes_sent": 2,
+                    "uptime": 3.5,
+                    "priority": 0,
+                }
+            }
+        })));
+        let mut e = Endpoint::attach_version(sock, RouterVersion::__v0_4_4);
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        e.set_schema_drift_hook({
+            let seen = seen.clone();
+            move |request, field| seen.lock().unwrap().push(format!("{request}.{field}"))
+        });
+        let peers = e.get_peers().unwrap().unwrap();
+        assert_eq!(peers[0].extra["priority"], 0);
+        assert_eq!(*seen.lock().unwrap(), ["getpeers.priority"]);
+    }
+
+    #[test]
+    fn v0_3() {
+        let sock = mock_reader!(
+            1 => &success(serde_json::json!({
+                "self": {
+                    "200:1234::1": {
+                        "box_pub_key": "abcd",
+                        "build_name": "yggdrasil",
+                        "build_version": "0.3.16",
+                        "coords": "[1 2]",
+                        "subnet": "300:1234::/64",
+                    }
+                }
+            })),
+            2 => &success(serde_json::json!({
+                "sessions": {
+                    "200:5678::1": {
+                        "box_pub_key": "ef01",
+                        "bytes_recvd": 1,
+                        "bytes_sent": 2,
+                        "coords": "[1 3 5]",
+                        "mtu": 65535,
+                        "uptime": 3.5,
+                        "was_mtu_fixed": false,
+                    }
+                }
+            })),
+        );
+        let mut e = Endpoint::attach(sock);
+        assert_eq!(e.get_version(), RouterVersion::v0_3);
+        let paths = e.get_paths().unwrap().unwrap();
+        assert_eq!(paths[0].key, "ef01");
+        assert_eq!(paths[0].path, [1, 3, 5]);
+    }
+
+    #[test]
+    fn allowed_public_keys() {
+        let sock = mock_reader!(
+            1 => &{
+                let json = serde_json::json!({
+                    "status": "success",
+                    "response": {
+                        "allowed_box_pubs": ["abcd"],
+                    }
+                });
+                let mut vec = serde_json::to_vec(&json).unwrap();
+                vec.push(b'\n');
+                vec
+            }
+        );
+        let mut e = Endpoint::attach_version(sock, RouterVersion::v0_3);
+        assert_eq!(e.get_allowed_public_keys().unwrap().unwrap(), ["abcd"]);
+
+        // Only v0.3 routers have these calls, nothing is sent to newer ones
+        for version in [
+            RouterVersion::__v0_4_4,
+            RouterVersion::v0_4_5__v0_4_7,
+            RouterVersion::v0_5_0__,
+        ] {
+            let mut e = Endpoint::attach_version(Cursor::new(Vec::new()), version);
+            let err = e.add_allowed_public_key("abcd".to_string()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Unsupported);
+            assert!(e.get_allowed_public_keys().is_err());
+            assert!(e.remove_allowed_public_key("abcd".to_string()).is_err());
+            assert!(e.socket.get_ref().is_empty());
+        }
+    }
+
+    #[test]
+    fn read_response() {
+        use super::protocol::read_response;
+        let mut scratch = Vec::new();
+        let options = EndpointOptions::default();
+        assert_eq!(
+            read_response(&mut mock_reader!(1 => b"{ ... }", 2 => b""), &mut scratch, &options).unwrap(),
+            b"{ ... }"
+        );
+        assert_eq!(
+            read_response(&mut mock_reader!(1 => b"{ ... }\n"), &mut scratch, &options).unwrap(),
+            b"{ ... }\n"
+        );
+        assert_eq!(
+            read_response(&mut mock_reader!(1 => b"{\n ... \n}\n"), &mut scratch, &options).unwrap(),
+            b"{\n ... \n}\n"
+        );
+        assert_eq!(
+            read_response(
+                &mut mock_reader!(
+                    1 => b"{",
+                    2 => b" ... ",
+                    3 => b"}\n",
+                ),
+                &mut scratch,
+                &options,
+            )
+            .unwrap(),
+            b"{ ... }\n"
+        );
+
+        let line: String = std::iter::repeat_n('a', 100).collect();
+        let lines: String = std::iter::repeat_n(format!("\"{line}\",\n"), 1000).collect();
+        let long = format!("{{\n{lines}\n}}\n");
+        assert!(long.len() > 8192 << 2);
+        assert_eq!(
+            read_response(&mut Cursor::new(long.as_bytes()), &mut scratch, &options).unwrap(),
+            long.as_bytes(),
+        );
+    }
+}
+}
+
+#[cfg(test)]
+mod tests_live {
+    use super::*;
+    const SOCKET_PATH: &str = env!("YGGDRASIL_SOCKET");
+
+    if_blocking! {
+    #[test]
+    fn test_request() {
+        let e = std::os::unix::net::UnixStream::connect(SOCKET_PATH).unwrap();
+        request(e);
+    }
+    }
+
+    if_async! {
+    #[cfg(feature = "use_tokio")]
+    #[tokio::test]
+    async fn test_request() {
+        let e = tokio::net::UnixStream::connect(SOCKET_PATH).await.unwrap();
+        request(e).await;
+    }
+
+    #[cfg(feature = "use_async_std")]
+    #[test]
+    fn test_request_async_std() {
+        async_std::task::block_on(async {
+            let e = Endpoint::<async_std::os::unix::net::UnixStream>::connect_unix(SOCKET_PATH)
+                .await
+                .unwrap();
+            request(e.into_inner()).await;
+        });
+    }
+
+    #[cfg(feature = "use_smol")]
+    #[test]
+    fn test_request_smol() {
+        smol::block_on(async {
+            let e = Endpoint::<smol::net::unix::UnixStream>::connect_unix(SOCKET_PATH)
+                .await
+                .unwrap();
+            request(e.into_inner()).await;
+        });
+    }
+
+    #[cfg(feature = "use_futures")]
+    #[test]
+    fn test_request() {
+        let e = futures::io::AllowStdIo::new(
+            std::os::unix::net::UnixStream::connect(SOCKET_PATH).unwrap(),
+        );
+        futures::executor::block_on(request(e));
+    }
+    }
+
+    #[maybe_async]
+    async fn request<S: AsyncWrite + AsyncRead + Unpin>(e: S) {
+        let mut e = Endpoint::attach(e).await;
+        // Keep tests strict about the schema
+        e.set_schema_drift_hook(|request, field| panic!("Unknown field {field:?} in {request:?}"));
+
+        if let RouterVersion::v0_4_5__v0_4_7 = e.get_version() {
+            e.get_dht().await.unwrap().unwrap();
+        }
+
+        if let RouterVersion::v0_4_5__v0_4_7 | RouterVersion::v0_5_0__ = e.get_version() {
+            #[derive(Debug, Deserialize)]
+            struct _SelfEntry {
+                build_name: String,
+            }
+            let err = e.request::<_SelfEntry>("getself").await;
+            assert!(!err.unwrap().unwrap().build_name.is_empty());
+
+            e.remove_peer("tcp://[::]:0".to_string(), None).await.ok();
+            e.add_peer("tcp://[::]:0".to_string(), None)
+                .await
+                .unwrap()
+                .unwrap();
+            e.remove_peer("tcp://[::]:0".to_string(), None)
+                .await
+                .unwrap()
+                .unwrap();
+            e.get_tun().await.unwrap().unwrap();
+        }
+
+        if let RouterVersion::v0_5_0__ = e.get_version() {
+            e.get_tree().await.unwrap().unwrap();
+            e.stream_tree(|_| ()).await.unwrap().unwrap();
+            e.stream_peers(|_| ()).await.unwrap().unwrap();
+        }
+
+        e.get_peers().await.unwrap().unwrap();
+        e.get_sessions().await.unwrap().unwrap();
+        e.get_self().await.unwrap().unwrap();
+        e.get_paths().await.unwrap().unwrap();
+        e.get_node_info("").await.unwrap().ok();
+        e.get_multicast_interfaces().await.unwrap().unwrap();
+        e.list().await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use super::*;
+use crate::{CachedNodeInfo, NodeInfo};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+if_async! {
+    use std::{future::Future, pin::Pin, task::Poll};
+}
+
+/// Resolves node info of many keys at once.
+///
+/// Lookups are issued concurrently, one per pooled endpoint.
+/// Both successful and failed lookups are cached, each with its own TTL.
+///
+/// Endpoints closed after an I/O error are skipped until reconnected with `replace_socket`.
+#[derive(Debug)]
+pub struct NodeInfoResolver<S> {
+    endpoints: Vec<Endpoint<S>>,
+    queue: VecDeque<String>,
+    cache: HashMap<String, CachedNodeInfo>,
+    success_ttl: Duration,
+    failure_ttl: Duration,
+}
+
+impl<S: AsyncWrite + AsyncRead + Unpin> NodeInfoResolver<S> {
+    /// Number of `endpoints` sets the number of concurrent lookups.
+    pub fn new(endpoints: Vec<Endpoint<S>>) -> Self {
+        Self {
+            endpoints,
+            queue: VecDeque::new(),
+            cache: HashMap::new(),
+            success_ttl: Duration::from_secs(60 * 60),
+            failure_ttl: Duration::from_secs(5 * 60),
+        }
+    }
+
+    pub fn with_ttl(mut self, success_ttl: Duration, failure_ttl: Duration) -> Self {
+        self.success_ttl = success_ttl;
+        self.failure_ttl = failure_ttl;
+        self
+    }
+
+    pub fn into_endpoints(self) -> Vec<Endpoint<S>> {
+        self.endpoints
+    }
+
+    pub fn get_endpoints(&self) -> &[Endpoint<S>] {
+        &self.endpoints
+    }
+
+    /// Indices of endpoints closed after an I/O error, see `replace_socket`.
+    pub fn closed_endpoints(&self) -> Vec<usize> {
+        self.endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.get_socket_state() == SocketState::Closed)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Reconnect endpoint at `index` with a new socket, see `Endpoint::replace_socket`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn replace_socket(&mut self, index: usize, socket: S) -> S {
+        self.endpoints[index].replace_socket(socket)
+    }
+
+    /// Schedule lookup of `key`, unless it's cached and not expired or already queued.
+    pub fn queue(&mut self, key: &str) {
+        if !self.is_expired(key) || self.queue.iter().any(|k| k == key) {
+            return;
+        }
+        self.queue.push_back(key.to_string());
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Cached result, possibly expired.
+    pub fn get(&self, key: &str) -> Option<&Result<NodeInfo, String>> {
+        self.cache.get(key).map(|e| &e.result)
+    }
+
+    pub fn is_expired(&self, key: &str) -> bool {
+        let Some(entry) = self.cache.get(key) else {
+            return true;
+        };
+        let ttl = match entry.result {
+            Ok(_) => self.success_ttl,
+            Err(_) => self.failure_ttl,
+        };
+        match SystemTime::now().duration_since(entry.time) {
+            Ok(age) => age >= ttl,
+            // Clock went backwards
+            Err(_) => true,
+        }
+    }
+
+    /// Merge cache previously stored with `save_cache`.
+    /// Entries already present in memory take precedence.
+    pub fn load_cache(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let cache: HashMap<String, CachedNodeInfo> = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        for (key, entry) in cache {
+            self.cache.entry(key).or_insert(entry);
+        }
+        Ok(())
+    }
+
+    pub fn save_cache(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_vec(&self.cache)?)
+    }
+
+    if_blocking! {
+    /// Look up all queued keys.
+    ///
+    /// An endpoint failing with I/O error leaves its key to the other endpoints and is skipped from then on.
+    /// The first such error is returned, along with keys that weren't resolved left in the queue.
+    pub fn resolve(&mut self) -> io::Result<()>
+    where
+        S: Send,
+    {
+        let queue = Mutex::new(std::mem::take(&mut self.queue));
+        let resolved = Mutex::new(Vec::new());
+        let results: Vec<io::Result<()>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .endpoints
+                .iter_mut()
+                .filter(|e| e.get_socket_state() == SocketState::Open)
+                .map(|endpoint| scope.spawn(|| worker(endpoint, &queue, &resolved)))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("Node info worker panicked"))
+                .collect()
+        });
+        self.finish(queue, resolved, results)
+    }
+    }
+
+    if_async! {
+    /// Look up all queued keys.
+    ///
+    /// An endpoint failing with I/O error leaves its key to the other endpoints and is skipped from then on.
+    /// The first such error is returned, along with keys that weren't resolved left in the queue.
+    pub async fn resolve(&mut self) -> io::Result<()> {
+        let queue = Mutex::new(std::mem::take(&mut self.queue));
+        let resolved = Mutex::new(Vec::new());
+        let workers = self
+            .endpoints
+            .iter_mut()
+            .filter(|e| e.get_socket_state() == SocketState::Open)
+            .map(|endpoint| {
+                Box::pin(worker(endpoint, &queue, &resolved))
+                    as Pin<Box<dyn Future<Output = io::Result<()>> + '_>>
+            })
+            .collect();
+        let results = join_all(workers).await;
+        self.finish(queue, resolved, results)
+    }
+    }
+
+    fn finish(
+        &mut self,
+        queue: Mutex<VecDeque<String>>,
+        resolved: Mutex<Vec<(String, CachedNodeInfo)>>,
+        results: Vec<io::Result<()>>,
+    ) -> io::Result<()> {
+        self.cache
+            .extend(resolved.into_inner().expect("Node info worker panicked"));
+        self.queue = queue.into_inner().expect("Node info worker panicked");
+        results.into_iter().collect::<io::Result<()>>()?;
+        if !self.queue.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotConnected,
+                "No open endpoints to resolve node info with, see `NodeInfoResolver::replace_socket`",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[maybe_async]
+async fn worker<S: AsyncWrite + AsyncRead + Unpin>(
+    endpoint: &mut Endpoint<S>,
+    queue: &Mutex<VecDeque<String>>,
+    resolved: &Mutex<Vec<(String, CachedNodeInfo)>>,
+) -> io::Result<()> {
+    loop {
+        let Some(key) = queue.lock().expect("Node info worker panicked").pop_front() else {
+            return Ok(());
+        };
+        match endpoint.get_node_info(&key).await {
+            Ok(result) => {
+                let entry = CachedNodeInfo {
+                    time: SystemTime::now(),
+                    result,
+                };
+                resolved
+                    .lock()
+                    .expect("Node info worker panicked")
+                    .push((key, entry));
+            }
+            Err(err) => {
+                // Connection is likely broken, leave the rest to other workers
+                queue
+                    .lock()
+                    .expect("Node info worker panicked")
+                    .push_back(key);
+                return Err(err);
+            }
+        }
+    }
+}
+
+if_async! {
+async fn join_all<'a, T>(mut futures: Vec<Pin<Box<dyn Future<Output = T> + 'a>>>) -> Vec<T> {
+    let mut results: Vec<Option<T>> = futures.iter().map(|_| None).collect();
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, result) in futures.iter_mut().zip(results.iter_mut()) {
+            if result.is_some() {
+                continue;
+            }
+            match future.as_mut().poll(cx) {
+                Poll::Ready(v) => *result = Some(v),
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+    results.into_iter().map(Option::unwrap).collect()
+}
+}
+
+if_blocking! {
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        io::{Cursor, Read, Write},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    /// Socket answering `getnodeinfo` in place of the router, keys starting with `unreachable` fail.
+    struct MockRouter {
+        request: Vec<u8>,
+        response: Cursor<Vec<u8>>,
+        lookups: Arc<AtomicUsize>,
+        broken: bool,
+        /// Set by a broken socket once it fails, others wait for it before answering
+        failed: Option<Arc<AtomicBool>>,
+    }
+
+    impl MockRouter {
+        fn new(lookups: &Arc<AtomicUsize>) -> Self {
+            Self {
+                request: Vec::new(),
+                response: Cursor::new(Vec::new()),
+                lookups: lookups.clone(),
+                broken: false,
+                failed: None,
+            }
+        }
+    }
+
+    impl Write for MockRouter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.request.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for MockRouter {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(failed) = &self.failed {
+                match self.broken {
+                    true => failed.store(true, Ordering::Relaxed),
+                    false => {
+                        while !failed.load(Ordering::Relaxed) {
+                            std::thread::yield_now();
+                        }
+                    }
+                }
+            }
+            if self.broken {
+                return Err(ErrorKind::ConnectionReset.into());
+            }
+            if self.response.position() == self.response.get_ref().len() as u64 {
+                let request: Value = serde_json::from_slice(&std::mem::take(&mut self.request))?;
+                let key = request["arguments"]["key"].as_str().unwrap_or_default();
+                let result = match key.starts_with("unreachable") {
+                    true => Err("timeout".to_string()),
+                    false => Ok(serde_json::json!({ key: { "name": key } })),
+                };
+                self.lookups.fetch_add(1, Ordering::Relaxed);
+                self.response = Cursor::new(crate::server::respond(request, result)?);
+            }
+            self.response.read(buf)
+        }
+    }
+
+    fn resolver(endpoints: usize) -> (NodeInfoResolver<MockRouter>, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let endpoints = (0..endpoints)
+            .map(|_| Endpoint::attach_version(MockRouter::new(&lookups), RouterVersion::v0_5_0__))
+            .collect();
+        (NodeInfoResolver::new(endpoints), lookups)
+    }
+
+    #[test]
+    fn resolve() {
+        let (resolver, lookups) = resolver(2);
+        let mut resolver = resolver.with_ttl(Duration::from_secs(60), Duration::ZERO);
+        for key in ["a", "b", "unreachable", "a"] {
+            resolver.queue(key);
+        }
+        resolver.resolve().unwrap();
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+        assert_eq!(resolver.queued(), 0);
+        let info = resolver.get("a").unwrap().as_ref().unwrap();
+        assert_eq!(info.name(), Some("a"));
+        assert_eq!(resolver.get("unreachable").unwrap(), &Err("timeout".to_string()));
+
+        // Failures expire on their own TTL
+        assert!(!resolver.is_expired("a"));
+        assert!(resolver.is_expired("unreachable"));
+        for key in ["a", "b", "unreachable"] {
+            resolver.queue(key);
+        }
+        assert_eq!(resolver.queue, ["unreachable"]);
+        resolver.resolve().unwrap();
+        assert_eq!(lookups.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn broken_endpoint() {
+        let (mut resolver, lookups) = resolver(2);
+        let failed = Arc::new(AtomicBool::new(false));
+        resolver.endpoints[0].get_mut().broken = true;
+        for endpoint in &mut resolver.endpoints {
+            endpoint.get_mut().failed = Some(failed.clone());
+        }
+        for key in ["a", "b", "c"] {
+            resolver.queue(key);
+        }
+        // Key of the broken endpoint is left to the other one
+        let err = resolver.resolve().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        assert_eq!(resolver.queued(), 0);
+        assert_eq!(resolver.closed_endpoints(), [0]);
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+
+        // Closed endpoint is skipped
+        resolver.queue("d");
+        resolver.resolve().unwrap();
+        assert_eq!(lookups.load(Ordering::Relaxed), 4);
+        assert!(["a", "b", "c", "d"].iter().all(|k| resolver.get(k).is_some()));
+
+        resolver.replace_socket(0, MockRouter::new(&lookups));
+        assert!(resolver.closed_endpoints().is_empty());
+        resolver.queue("e");
+        resolver.resolve().unwrap();
+        assert_eq!(lookups.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn cache() {
+        let path = std::env::temp_dir().join(format!(
+            "yggdrasilctl-resolver-{}.json",
+            std::process::id()
+        ));
+        let (mut saved, _) = resolver(1);
+        for key in ["a", "unreachable"] {
+            saved.queue(key);
+        }
+        saved.resolve().unwrap();
+        saved.save_cache(&path).unwrap();
+
+        let (mut loaded, lookups) = resolver(1);
+        let entry = CachedNodeInfo {
+            time: SystemTime::now(),
+            result: Err("newer".to_string()),
+        };
+        loaded.cache.insert("a".to_string(), entry.clone());
+        loaded.load_cache(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Entries in memory take precedence
+        assert_eq!(loaded.cache["a"], entry);
+        assert_eq!(loaded.cache["unreachable"], saved.cache["unreachable"]);
+        assert!(!loaded.is_expired("unreachable"));
+        loaded.queue("unreachable");
+        assert_eq!(loaded.queued(), 0);
+        assert_eq!(lookups.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn queue() {
+        let mut resolver = NodeInfoResolver::<Cursor<Vec<u8>>>::new(Vec::new())
+            .with_ttl(Duration::from_secs(60), Duration::ZERO);
+        resolver.cache.insert(
+            "ok".to_string(),
+            CachedNodeInfo {
+                time: SystemTime::now(),
+                result: Ok(NodeInfo::default()),
+            },
+        );
+        resolver.cache.insert(
+            "err".to_string(),
+            CachedNodeInfo {
+                time: SystemTime::now(),
+                result: Err("timeout".to_string()),
+            },
+        );
+        for key in ["ok", "err", "new", "new"] {
+            resolver.queue(key);
+        }
+        assert_eq!(resolver.queue, ["err", "new"]);
+        assert_eq!(
+            resolver.resolve().unwrap_err().kind(),
+            ErrorKind::NotConnected
+        );
+        assert_eq!(resolver.queued(), 2);
+    }
+}
+}
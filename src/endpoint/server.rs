@@ -0,0 +1,317 @@
+use super::*;
+use crate::{
+    protocol,
+    server::{respond, Handler, RequestScanner, DEFAULT_MAX_CONNECTIONS, DEFAULT_MAX_REQUEST_SIZE},
+};
+
+pub struct Server {
+    handlers: Vec<Box<dyn Handler>>,
+    max_request_size: usize,
+    max_connections: usize,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            handlers: Vec::new(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Larger requests are answered with an error and the connection is closed.
+    /// `DEFAULT_MAX_REQUEST_SIZE` by default.
+    pub fn set_max_request_size(&mut self, max: usize) {
+        self.max_request_size = max;
+    }
+
+    pub fn get_max_request_size(&self) -> usize {
+        self.max_request_size
+    }
+
+    /// Connections served at once by `serve`, further ones wait to be accepted.
+    /// `DEFAULT_MAX_CONNECTIONS` by default.
+    pub fn set_max_connections(&mut self, max: usize) {
+        self.max_connections = max.max(1);
+    }
+
+    pub fn get_max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Replaces a handler with the same name, if any.
+    /// Requests named `list` are answered by the server itself.
+    pub fn register(&mut self, handler: impl Handler + 'static) {
+        self.handlers
+            .retain(|h| !h.name().eq_ignore_ascii_case(handler.name()));
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// Dispatch a decoded request to its handler.
+    pub fn dispatch(
+        &self,
+        request: &str,
+        arguments: &HashMap<String, Value>,
+    ) -> Result<Value, String> {
+        if request.eq_ignore_ascii_case("list") {
+            return Ok(self.list());
+        }
+        match self
+            .handlers
+            .iter()
+            .find(|h| h.name().eq_ignore_ascii_case(request))
+        {
+            Some(handler) => handler.handle(arguments),
+            None => Err(format!("unknown action '{request}', try 'list' for help")),
+        }
+    }
+
+    // Same shape as `list` of v0.5 routers
+    fn list(&self) -> Value {
+        let mut list: Vec<Value> = self
+            .handlers
+            .iter()
+            .map(|h| {
+                serde_json::json!({
+                    "command": h.name().to_ascii_lowercase(),
+                    "description": h.description(),
+                    "fields": h.fields(),
+                })
+            })
+            .collect();
+        list.push(serde_json::json!({
+            "command": "list",
+            "description": "List available commands",
+            "fields": [],
+        }));
+        serde_json::json!({ "list": list })
+    }
+
+    /// Serve requests from `socket` until the client closes the connection
+    /// or sends a request with `keepalive` unset.
+    #[maybe_async]
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        socket: S,
+    ) -> io::Result<()> {
+        serve_with(socket, self.max_request_size, |request, arguments| {
+            self.dispatch(request, arguments)
+        })
+        .await
+    }
+
+    if_blocking! {
+    /// Serve every connection of `incoming` on its own thread,
+    /// e.g. `server.serve(listener.incoming())` for `UnixListener` or `TcpListener`.
+    /// At most `get_max_connections` are served at once, the next one is accepted when one of them ends.
+    ///
+    /// Returns on the first error accepting a connection, errors of individual connections are ignored.
+    /// With async runtimes, spawn `serve_connection` for every accepted socket instead.
+    pub fn serve<S: AsyncRead + AsyncWrite + Unpin + Send>(
+        &self,
+        incoming: impl IntoIterator<Item = io::Result<S>>,
+    ) -> io::Result<()> {
+        serve_limited(incoming, self.max_connections, Err, |socket| {
+            self.serve_connection(socket).ok();
+        })
+    }
+    }
+}
+
+if_blocking! {
+/// Call `serve` for every connection of `incoming` on its own thread, at most `max` at once.
+/// Errors accepting a connection are passed to `on_error`, which decides whether to go on.
+pub(crate) fn serve_limited<S: Send>(
+    incoming: impl IntoIterator<Item = io::Result<S>>,
+    max: usize,
+    mut on_error: impl FnMut(io::Error) -> io::Result<()>,
+    serve: impl Fn(S) + Sync,
+) -> io::Result<()> {
+    use std::sync::{Condvar, Mutex};
+
+    // Frees the slot even if `serve` panics
+    struct Slot<'a>(&'a (Mutex<usize>, Condvar));
+    impl Drop for Slot<'_> {
+        fn drop(&mut self) {
+            let (active, freed) = self.0;
+            *active.lock().unwrap() -= 1;
+            freed.notify_one();
+        }
+    }
+
+    let slots = (Mutex::new(0), Condvar::new());
+    let serve = &serve;
+    std::thread::scope(|scope| {
+        let mut incoming = incoming.into_iter();
+        loop {
+            {
+                let (active, freed) = &slots;
+                let mut active = freed
+                    .wait_while(active.lock().unwrap(), |active| *active >= max)
+                    .unwrap();
+                *active += 1;
+            }
+            let slot = Slot(&slots);
+            let Some(socket) = incoming.next() else {
+                return Ok(());
+            };
+            let socket = match socket {
+                Ok(socket) => socket,
+                Err(err) => {
+                    on_error(err)?;
+                    continue;
+                }
+            };
+            scope.spawn(move || {
+                let _slot = slot;
+                serve(socket)
+            });
+        }
+    })
+}
+}
+
+/// Decode requests from `socket` and answer them with `dispatch(request, arguments)`.
+/// Requests larger than `max_request_size` are answered with an error, closing the connection.
+#[maybe_async]
+pub(crate) async fn serve_with<S: AsyncRead + AsyncWrite + Unpin>(
+    mut socket: S,
+    max_request_size: usize,
+    mut dispatch: impl FnMut(&str, &HashMap<String, Value>) -> Result<Value, String>,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = vec![0; 4096];
+    let mut scanner = RequestScanner::default();
+    loop {
+        while let Some(end) = scanner.next(&buf) {
+            if end > max_request_size {
+                break;
+            }
+            let request = match serde_json::from_slice::<protocol::Request>(&buf[..end]) {
+                Ok(request) => request,
+                Err(err) => {
+                    let response = respond(Value::Null, Err(format!("Invalid request: {err}")))?;
+                    return socket.write_all(&response).await;
+                }
+            };
+            let result = dispatch(&request.request, &request.arguments);
+            let response = respond(serde_json::to_value(&request)?, result)?;
+            let keepalive = request.keepalive;
+            socket.write_all(&response).await?;
+            buf.drain(..end);
+            scanner.reset();
+            if !keepalive {
+                return Ok(());
+            }
+        }
+        if buf.len() > max_request_size {
+            let error = format!("Request is larger than {max_request_size} bytes");
+            let response = respond(Value::Null, Err(error))?;
+            return socket.write_all(&response).await;
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+}
+
+if_blocking! {
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::RouterVersion;
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixStream,
+    };
+
+    struct Echo;
+
+    impl Handler for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+        fn fields(&self) -> Vec<String> {
+            vec!["value".to_string()]
+        }
+        fn handle(&self, arguments: &HashMap<String, Value>) -> Result<Value, String> {
+            match arguments.get("value") {
+                Some(value) => Ok(serde_json::json!({ "value": value })),
+                None => Err("Missing value".to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn server() {
+        let mut server = Server::new();
+        server.register(Echo);
+        let (client, socket) = UnixStream::pair().unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| server.serve_connection(socket).unwrap());
+
+            let mut e = Endpoint::attach_version(client, RouterVersion::v0_5_0__);
+            let list = e.list().unwrap().unwrap();
+            assert_eq!(list[0].command, "echo");
+            assert_eq!(list[0].fields.as_deref(), Some(&["value".to_string()][..]));
+            assert_eq!(list[1].command, "list");
+
+            let args = HashMap::from([("value".to_string(), Value::from(42))]);
+            let raw = e.request_raw("Echo", args.clone()).unwrap();
+            assert_eq!(raw.response, serde_json::json!({ "value": 42 }));
+            assert!(raw.is_echo_of("echo", &args));
+
+            let err = e.request::<Value>("echo").unwrap().unwrap_err();
+            assert_eq!(err, "Missing value");
+            let err = e.request::<Value>("missing").unwrap().unwrap_err();
+            assert!(err.starts_with("unknown action"));
+            // Server returns after a request without keepalive
+            e.set_request_mode(crate::RequestMode::OneShot);
+            e.request::<Value>("list").unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn limits() {
+        let mut server = Server::new();
+        server.register(Echo);
+        server.set_max_request_size(64);
+        server.set_max_connections(1);
+        let (mut client, socket) = UnixStream::pair().unwrap();
+        let (other, other_socket) = UnixStream::pair().unwrap();
+        std::thread::scope(|scope| {
+            let server = &server;
+            scope.spawn(move || server.serve([Ok(socket), Ok(other_socket)]).unwrap());
+
+            // Split across reads, within the limit
+            let mut e = Endpoint::attach_version(other, RouterVersion::v0_5_0__);
+            client.write_all(br#"{"request":"echo","#).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            client.write_all(br#""arguments":{"value":1},"keepalive":true}"#).unwrap();
+            let mut response = String::new();
+            while !response.ends_with('\n') {
+                let mut chunk = [0; 256];
+                let read = client.read(&mut chunk).unwrap();
+                response.push_str(std::str::from_utf8(&chunk[..read]).unwrap());
+            }
+            assert!(response.contains(r#""status": "success""#));
+
+            client.write_all(&[b' '; 100]).unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            assert!(response.contains("Request is larger than 64 bytes"));
+
+            // Served once the first connection is closed
+            assert_eq!(e.list().unwrap().unwrap().len(), 2);
+        });
+    }
+}
+}
@@ -0,0 +1,205 @@
+use super::*;
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostEntry {
+    pub name: String,
+    pub address: Ipv6Addr,
+    pub key: String,
+}
+
+/// Lowercase `name` and replace characters not allowed in host names.
+///
+/// Empty labels are dropped and long ones truncated to 63 characters,
+/// names longer than 253 characters are rejected.
+pub(crate) fn sanitize_hostname(name: &str) -> Option<String> {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '.') => c,
+            _ => '-',
+        })
+        .collect();
+    let labels: Vec<&str> = name
+        .split('.')
+        .map(|label| label.trim_matches('-'))
+        // Characters are ASCII at this point
+        .map(|label| label[..label.len().min(63)].trim_end_matches('-'))
+        .filter(|label| !label.is_empty())
+        .collect();
+    let name = labels.join(".");
+    (!name.is_empty() && name.len() <= 253).then_some(name)
+}
+
+/// Suffix names shared by several nodes with the start of their keys, so every name is unique.
+///
+/// Suffixes start at 8 characters and grow until they tell the nodes apart, as keys often
+/// share leading zeros. Names already taken by other nodes are skipped, numbering the suffix
+/// if the whole key doesn't help.
+pub(crate) fn disambiguate(entries: &mut [HostEntry]) {
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, e) in entries.iter().enumerate() {
+        groups.entry(e.name.clone()).or_default().push(i);
+    }
+    let mut taken: HashSet<String> = groups
+        .iter()
+        .filter(|(_, group)| group.len() == 1)
+        .map(|(name, _)| name.clone())
+        .collect();
+    for (name, group) in groups.into_iter().filter(|(_, group)| group.len() > 1) {
+        let keys: Vec<String> = group.iter().map(|&i| entries[i].key.clone()).collect();
+        let prefix = |key: &str, len: usize| -> String { key.chars().take(len).collect() };
+        let longest = keys
+            .iter()
+            .map(|key| key.chars().count())
+            .max()
+            .unwrap_or(0);
+        let names = (8..=longest.max(8)).find_map(|len| {
+            let names = keys
+                .iter()
+                .map(|key| suffixed(&name, &prefix(key, len)))
+                .collect::<Option<Vec<String>>>()?;
+            let unique: HashSet<&String> = names.iter().collect();
+            (unique.len() == names.len() && !names.iter().any(|n| taken.contains(n)))
+                .then_some(names)
+        });
+        if let Some(names) = names {
+            for (&i, name) in group.iter().zip(names) {
+                taken.insert(name.clone());
+                entries[i].name = name;
+            }
+            continue;
+        }
+        // Same keys, or names too long to fit them
+        for (&i, key) in group.iter().zip(&keys) {
+            let key = prefix(key, 8);
+            let name = (1..=entries.len() + 1)
+                .filter_map(|n| suffixed(&name, &format!("{key}-{n}")))
+                .find(|name| !taken.contains(name));
+            if let Some(name) = name {
+                taken.insert(name.clone());
+                entries[i].name = name;
+            }
+        }
+    }
+}
+
+/// Append `suffix` to the last label of `name`, shortening the label to keep the suffix whole.
+fn suffixed(name: &str, suffix: &str) -> Option<String> {
+    let (head, last) = match name.rsplit_once('.') {
+        Some((head, last)) => (Some(head), last),
+        None => (None, name),
+    };
+    // Characters are ASCII after `sanitize_hostname`
+    let room = 63usize.saturating_sub(suffix.len() + 1);
+    let last = &last[..last.len().min(room)];
+    let name = match head {
+        Some(head) => format!("{head}.{last}-{suffix}"),
+        None => format!("{last}-{suffix}"),
+    };
+    sanitize_hostname(&name)
+}
+
+/// Format entries as `/etc/hosts` lines.
+pub fn format_hosts(entries: &[HostEntry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        let _ = writeln!(out, "{}\t{}", e.address, e.name);
+    }
+    out
+}
+
+/// Format entries as an RFC 1035 zone fragment of `AAAA` records relative to `origin`.
+pub fn format_zone(entries: &[HostEntry], origin: Option<&str>, ttl: Option<u32>) -> String {
+    let mut out = String::new();
+    if let Some(origin) = origin {
+        let _ = writeln!(out, "$ORIGIN {}.", origin.trim_end_matches('.'));
+    }
+    if let Some(ttl) = ttl {
+        let _ = writeln!(out, "$TTL {ttl}");
+    }
+    for e in entries {
+        let _ = writeln!(out, "{}\tIN\tAAAA\t{}", e.name, e.address);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(
+            sanitize_hostname(" My Node#1 ").as_deref(),
+            Some("my-node-1")
+        );
+        assert_eq!(sanitize_hostname("--"), None);
+        assert_eq!(sanitize_hostname("a..-b-.").as_deref(), Some("a.b"));
+        let label = "x".repeat(70);
+        assert_eq!(sanitize_hostname(&label), Some("x".repeat(63)));
+        assert_eq!(sanitize_hostname(&[&label[..60]; 5].join(".")), None);
+
+        let entry = |name: &str, key: &str| HostEntry {
+            name: name.to_string(),
+            address: "200::1".parse().unwrap(),
+            key: key.to_string(),
+        };
+        let mut entries = [
+            entry("node", "0123456789"),
+            entry("node", "abcdef0123"),
+            entry("other", "ff"),
+        ];
+        disambiguate(&mut entries);
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["node-01234567", "node-abcdef01", "other"]);
+
+        // Keys sharing leading zeros, and a suffixed name taken by another node
+        let mut entries = [
+            entry("node", "000000000a12"),
+            entry("node", "000000000b34"),
+            entry("node-000000000a", "ff"),
+            entry("same", "0123456789"),
+            entry("same", "0123456789"),
+        ];
+        disambiguate(&mut entries);
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "node-000000000a1",
+                "node-000000000b3",
+                "node-000000000a",
+                "same-01234567-1",
+                "same-01234567-2"
+            ]
+        );
+        let long = entry(&"x".repeat(63), "0123456789");
+        let mut entries = [
+            long.clone(),
+            HostEntry {
+                key: "0123456799".to_string(),
+                ..long
+            },
+        ];
+        disambiguate(&mut entries);
+        assert_eq!(entries[0].name, format!("{}-012345678", "x".repeat(53)));
+        assert_eq!(entries[1].name, format!("{}-012345679", "x".repeat(53)));
+
+        let entries = [HostEntry {
+            name: "node".to_string(),
+            address: "200::1".parse().unwrap(),
+            key: String::new(),
+        }];
+        assert_eq!(format_hosts(&entries), "200::1\tnode\n");
+        assert_eq!(
+            format_zone(&entries, Some("ygg.example."), Some(300)),
+            "$ORIGIN ygg.example.\n$TTL 300\nnode\tIN\tAAAA\t200::1\n"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Node identity: keys and the addresses derived from them.
+//!
+//! Key pair generation requires the `keygen` feature.
+
+use std::net::Ipv6Addr;
+
+#[cfg(feature = "keygen")]
+use std::{
+    io::{self, Error, ErrorKind},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::Mutex,
+};
+
+/// Yggdrasil address of a node with the given public key.
+pub fn address_for_key(public_key: &[u8; 32]) -> Ipv6Addr {
+    let mut addr = [0u8; 16];
+    addr[0] = 0x02;
+    let (mut ones, mut done, mut bits, mut n_bits, mut out) = (0u8, false, 0u8, 0, 2);
+    for idx in 0..256 {
+        // Address is derived from the inverted key
+        let bit = (!public_key[idx / 8] >> (7 - idx % 8)) & 1;
+        if !done {
+            if bit == 1 {
+                ones = ones.wrapping_add(1);
+            } else {
+                done = true;
+            }
+            continue;
+        }
+        bits = (bits << 1) | bit;
+        n_bits += 1;
+        if n_bits == 8 {
+            n_bits = 0;
+            if out < addr.len() {
+                addr[out] = bits;
+                out += 1;
+            }
+        }
+    }
+    addr[1] = ones;
+    Ipv6Addr::from(addr)
+}
+
+/// Routed /64 subnet of a node with the given public key, e.g. `300:1234:5678:9abc::/64`.
+pub fn subnet_for_key(public_key: &[u8; 32]) -> String {
+    let mut subnet = [0u8; 16];
+    subnet[..8].copy_from_slice(&address_for_key(public_key).octets()[..8]);
+    subnet[0] |= 0x01;
+    format!("{}/64", Ipv6Addr::from(subnet))
+}
+
+/// Number of leading one bits of the inverted key, a.k.a. address "strength".
+pub fn key_strength(public_key: &[u8; 32]) -> u8 {
+    address_for_key(public_key).octets()[1]
+}
+
+/// Decode hex-encoded public key as found in `SelfEntry::key` and other entries.
+pub fn parse_public_key(key: &str) -> Option<[u8; 32]> {
+    decode_hex(key)?.try_into().ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg_attr(not(any(test, feature = "keygen")), allow(dead_code))]
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Key pair of a node with the derived addresses.
+/// Keys are hex-encoded the same way as in the router config and Admin API.
+#[cfg(feature = "keygen")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    /// 64 bytes, private seed followed by the public key, as in `PrivateKey` of the config
+    pub private_key: String,
+    /// 32 bytes, as in `SelfEntry::key`
+    pub public_key: String,
+    pub address: Ipv6Addr,
+    pub subnet: String,
+}
+
+#[cfg(feature = "keygen")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum VanityTarget {
+    /// Textual address starts with the given prefix, e.g. `203:`
+    Prefix(String),
+    /// Key strength is at least the given number of bits
+    Strength(u8),
+}
+
+#[cfg(feature = "keygen")]
+impl VanityTarget {
+    fn matches(&self, public_key: &[u8; 32]) -> bool {
+        match self {
+            VanityTarget::Prefix(prefix) => address_for_key(public_key)
+                .to_string()
+                .starts_with(prefix.as_str()),
+            VanityTarget::Strength(strength) => key_strength(public_key) >= *strength,
+        }
+    }
+}
+
+#[cfg(feature = "keygen")]
+impl Identity {
+    pub fn generate() -> io::Result<Self> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed).map_err(|err| Error::other(err.to_string()))?;
+        Ok(Self::from_seed(&seed))
+    }
+
+    /// Derive identity from a 64-byte private key or its 32-byte seed.
+    pub fn from_private_key(private_key: &str) -> io::Result<Self> {
+        let invalid = |msg| Error::new(ErrorKind::InvalidInput, msg);
+        let bytes = decode_hex(private_key).ok_or_else(|| invalid("Private key is not hex"))?;
+        let seed: [u8; 32] = match bytes.len() {
+            32 | 64 => bytes[..32].try_into().unwrap(),
+            _ => return Err(invalid("Private key must be 32 or 64 bytes long")),
+        };
+        let identity = Self::from_seed(&seed);
+        if bytes.len() == 64 && encode_hex(&bytes[32..]) != identity.public_key {
+            return Err(invalid("Private key doesn't match its public part"));
+        }
+        Ok(identity)
+    }
+
+    fn from_seed(seed: &[u8; 32]) -> Self {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(seed);
+        let public_key = signing_key.verifying_key().to_bytes();
+        Self {
+            private_key: encode_hex(&signing_key.to_keypair_bytes()),
+            public_key: encode_hex(&public_key),
+            address: address_for_key(&public_key),
+            subnet: subnet_for_key(&public_key),
+        }
+    }
+
+    /// Generate keys on `threads` threads until one matches `target`.
+    /// Returns `None` if nothing was found in `max_attempts` keys.
+    pub fn search(
+        target: &VanityTarget,
+        threads: usize,
+        max_attempts: Option<u64>,
+    ) -> io::Result<Option<Self>> {
+        let found = Mutex::new(None);
+        let stop = AtomicBool::new(false);
+        let attempts = AtomicU64::new(0);
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.max(1))
+                .map(|_| {
+                    scope.spawn(|| -> io::Result<()> {
+                        let mut seed = [0u8; 32];
+                        while !stop.load(Ordering::Relaxed) {
+                            let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                            if max_attempts.is_some_and(|max| attempt >= max) {
+                                break;
+                            }
+                            getrandom::getrandom(&mut seed)
+                                .map_err(|err| Error::other(err.to_string()))?;
+                            let signing_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+                            if target.matches(&signing_key.verifying_key().to_bytes()) {
+                                stop.store(true, Ordering::Relaxed);
+                                *found.lock().unwrap() = Some(Self::from_seed(&seed));
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("Key search worker panicked"))
+        })?;
+        Ok(found.into_inner().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address() {
+        let mut key = [0xaau8; 32];
+        key[..3].copy_from_slice(&[0x00, 0x00, 0x7f]);
+        // Inverted key starts with 17 ones and a zero, followed by 0b000000 and 0x55 repeated
+        let addr = address_for_key(&key);
+        assert_eq!(addr.octets()[..4], [0x02, 17, 0x01, 0x55]);
+        assert_eq!(key_strength(&key), 17);
+        assert_eq!(subnet_for_key(&key), "311:155:5555:5555::/64");
+        assert_eq!(parse_public_key(&encode_hex(&key)), Some(key));
+        assert_eq!(parse_public_key("abc"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "keygen")]
+    fn identity() {
+        // RFC 8032, test 1
+        let seed = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+        let public_key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        let identity = Identity::from_private_key(seed).unwrap();
+        assert_eq!(identity.public_key, public_key);
+        assert_eq!(identity.private_key, format!("{seed}{public_key}"));
+        assert_eq!(
+            Identity::from_private_key(&identity.private_key).unwrap(),
+            identity
+        );
+        assert!(Identity::from_private_key(&format!("{seed}{seed}")).is_err());
+
+        let target = VanityTarget::Strength(4);
+        let found = Identity::search(&target, 2, None).unwrap().unwrap();
+        assert!(found.address.octets()[1] >= 4);
+        assert_eq!(Identity::search(&target, 1, Some(0)).unwrap(), None);
+    }
+}
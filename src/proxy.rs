@@ -0,0 +1,538 @@
+//! Admin API proxy enforcing an access policy, e.g. to give monitoring users read-only access.
+//!
+//! ```rust,ignore
+//! use yggdrasilctl::proxy::{Policy, Proxy};
+//!
+//! let proxy = Proxy::new(|| UnixStream::connect("/var/run/yggdrasil.sock"), Policy::read_only());
+//! proxy.serve_unix(&UnixListener::bind("/run/yggdrasil-ro.sock")?)?;
+//! ```
+
+use crate::{
+    blocking::{serve_limited, serve_with},
+    server::{DEFAULT_MAX_CONNECTIONS, DEFAULT_MAX_REQUEST_SIZE},
+    Endpoint,
+};
+use serde_json::Value;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::{
+    collections::HashMap,
+    io::{self, Error, ErrorKind, Read, Write},
+    net::TcpListener,
+    sync::{mpsc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, Instant},
+};
+
+/// Ordered list of rules, the first rule matching a request decides.
+/// Requests matching no rule are denied.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub allow: bool,
+    /// Request name pattern, `*` matches any sequence and `?` any single character
+    pub pattern: String,
+    /// Only match clients of this user, known for Unix sockets only
+    pub uid: Option<u32>,
+}
+
+impl Policy {
+    /// Allow `get*` requests and `list` to everyone.
+    pub fn read_only() -> Self {
+        Self {
+            rules: vec![Rule::allow("get*"), Rule::allow("list")],
+        }
+    }
+
+    /// Parse rules, one per line:
+    ///
+    /// ```text
+    /// # Root may do anything
+    /// uid 0 allow *
+    /// deny getnodeinfo
+    /// allow get* list
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut rules = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let invalid = |msg: &str| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{msg} at line {}: {line:?}", n + 1),
+                )
+            };
+            let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+            let Some(mut word) = words.next() else {
+                continue;
+            };
+            let mut uid = None;
+            if word == "uid" {
+                let value = words.next().and_then(|uid| uid.parse().ok());
+                uid = Some(value.ok_or_else(|| invalid("Expected user ID"))?);
+                word = words.next().unwrap_or("");
+            }
+            let allow = match word {
+                "allow" => true,
+                "deny" => false,
+                _ => return Err(invalid("Expected `allow` or `deny`")),
+            };
+            let start = rules.len();
+            rules.extend(words.map(|pattern| Rule {
+                allow,
+                pattern: pattern.to_string(),
+                uid,
+            }));
+            if rules.len() == start {
+                return Err(invalid("Expected request pattern"));
+            }
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn is_allowed(&self, request: &str, uid: Option<u32>) -> bool {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(request, uid))
+            .is_some_and(|rule| rule.allow)
+    }
+}
+
+impl Rule {
+    pub fn allow(pattern: &str) -> Self {
+        Self {
+            allow: true,
+            pattern: pattern.to_string(),
+            uid: None,
+        }
+    }
+
+    pub fn deny(pattern: &str) -> Self {
+        Self {
+            allow: false,
+            ..Self::allow(pattern)
+        }
+    }
+
+    pub fn matches(&self, request: &str, uid: Option<u32>) -> bool {
+        if self.uid.is_some() && self.uid != uid {
+            return false;
+        }
+        // Routers lowercase request names
+        let pattern = self.pattern.to_ascii_lowercase();
+        glob(pattern.as_bytes(), request.to_ascii_lowercase().as_bytes())
+    }
+}
+
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+type Connect<S> = Box<dyn Fn() -> io::Result<S> + Send + Sync>;
+type DeniedHook = Box<dyn Fn(&str, Option<u32>) + Send + Sync>;
+// Responses by request name, with the time they were received
+type Cache = HashMap<String, (Instant, Value)>;
+
+/// Requests served from the cache, see `Proxy::set_cache_interval`
+pub const CACHED_REQUESTS: &[&str] = &[
+    "getself",
+    "getpeers",
+    "getsessions",
+    "getpaths",
+    "gettree",
+    "gettun",
+];
+
+/// Requests of all clients are forwarded over a single connection to the router.
+pub struct Proxy<S> {
+    connect: Connect<S>,
+    // Connected lazily and dropped on I/O errors to be reconnected by the next request
+    upstream: Mutex<Option<Endpoint<S>>>,
+    // Checked before waiting for the upstream, which serves one request at a time
+    cache: RwLock<Cache>,
+    policy: Policy,
+    on_denied: Option<DeniedHook>,
+    cache_interval: Option<Duration>,
+}
+
+impl<S: Read + Write + Unpin + Send> Proxy<S> {
+    /// `connect` opens a connection to the router, it's called again after I/O errors.
+    pub fn new(
+        connect: impl Fn() -> io::Result<S> + Send + Sync + 'static,
+        policy: Policy,
+    ) -> Self {
+        Self {
+            connect: Box::new(connect),
+            upstream: Mutex::new(None),
+            cache: RwLock::new(HashMap::new()),
+            policy,
+            on_denied: None,
+            cache_interval: None,
+        }
+    }
+
+    /// Serve `CACHED_REQUESTS` without arguments from a cache shared by all clients.
+    /// Disabled by default.
+    ///
+    /// `serve_unix` and `serve_tcp` refresh cached responses every `interval` in the background,
+    /// see `refresh_cache`. Responses older than `interval` are never served,
+    /// the request is forwarded instead. Cache is cleared after successful `addpeer` and `removepeer`.
+    pub fn set_cache_interval(&mut self, interval: Option<Duration>) {
+        self.cache_interval = interval;
+    }
+
+    /// Set a function called as `hook(request, uid)` for every denied request, e.g. for logging.
+    pub fn on_denied(&mut self, hook: impl Fn(&str, Option<u32>) + Send + Sync + 'static) {
+        self.on_denied = Some(Box::new(hook));
+    }
+
+    /// Serve requests of a single client with user ID `uid`, if known.
+    pub fn serve_connection<C: Read + Write + Unpin>(
+        &self,
+        socket: C,
+        uid: Option<u32>,
+    ) -> io::Result<()> {
+        serve_with(socket, DEFAULT_MAX_REQUEST_SIZE, |request, arguments| {
+            self.handle(request, arguments, uid)
+        })
+    }
+
+    /// Serve every client on its own thread, identifying them with `SO_PEERCRED`.
+    /// At most `DEFAULT_MAX_CONNECTIONS` clients are served at once.
+    /// Errors accepting a client are logged, and never returned.
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: &UnixListener) -> io::Result<()> {
+        self.refreshing(|| {
+            serve_limited(
+                listener.incoming(),
+                DEFAULT_MAX_CONNECTIONS,
+                accept_failed,
+                |socket| {
+                    let uid = peer_uid(&socket).ok();
+                    self.serve_connection(socket, uid).ok();
+                },
+            )
+        })
+    }
+
+    /// Same as `serve_unix`, but rules with `uid` never match TCP clients.
+    pub fn serve_tcp(&self, listener: &TcpListener) -> io::Result<()> {
+        self.refreshing(|| {
+            serve_limited(
+                listener.incoming(),
+                DEFAULT_MAX_CONNECTIONS,
+                accept_failed,
+                |socket| {
+                    self.serve_connection(socket, None).ok();
+                },
+            )
+        })
+    }
+
+    /// Forward every cached request again, replacing its response.
+    /// Responses of failed requests are kept until they are too old to be served.
+    pub fn refresh_cache(&self) {
+        let requests: Vec<String> = self.cache_read().keys().cloned().collect();
+        let mut upstream = self.upstream.lock().unwrap_or_else(|err| err.into_inner());
+        for request in requests {
+            if let Ok(response) = self.request(&mut upstream, &request, &HashMap::new()) {
+                self.cache_write()
+                    .insert(request, (Instant::now(), response));
+            }
+        }
+    }
+
+    // Refresh the cache every interval while `serve` runs
+    fn refreshing(&self, serve: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let Some(interval) = self.cache_interval else {
+            return serve();
+        };
+        let (stop, stopped) = mpsc::channel::<()>();
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    self.refresh_cache();
+                }
+            });
+            let result = serve();
+            drop(stop);
+            result
+        })
+    }
+
+    fn handle(
+        &self,
+        request: &str,
+        arguments: &HashMap<String, Value>,
+        uid: Option<u32>,
+    ) -> Result<Value, String> {
+        if !self.policy.is_allowed(request, uid) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(target: "yggdrasilctl", request, uid, "Admin API request denied");
+            if let Some(hook) = &self.on_denied {
+                hook(request, uid);
+            }
+            return Err("Permission denied".to_string());
+        }
+        let mut response = self.forward(request, arguments)?;
+        if request.eq_ignore_ascii_case("list") {
+            // Hide requests the client isn't allowed to make
+            match response.get_mut("list") {
+                Some(Value::Array(list)) => list.retain(|entry| {
+                    let command = entry.get("command").and_then(Value::as_str);
+                    command.is_some_and(|command| self.policy.is_allowed(command, uid))
+                }),
+                // Routers before v0.4.5 key the list by request name
+                Some(Value::Object(list)) => {
+                    list.retain(|command, _| self.policy.is_allowed(command, uid))
+                }
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+
+    fn forward(&self, request: &str, arguments: &HashMap<String, Value>) -> Result<Value, String> {
+        let request = request.to_ascii_lowercase();
+        let cache_interval = self
+            .cache_interval
+            .filter(|_| arguments.is_empty() && CACHED_REQUESTS.contains(&request.as_str()));
+        let cached = |interval| {
+            let cache = self.cache_read();
+            let (time, response) = cache.get(&request)?;
+            (time.elapsed() < interval).then(|| response.clone())
+        };
+        if let Some(response) = cache_interval.and_then(cached) {
+            return Ok(response);
+        }
+
+        let mut upstream = self.upstream.lock().unwrap_or_else(|err| err.into_inner());
+        // Another client may have refreshed it while waiting for the upstream
+        if let Some(response) = cache_interval.and_then(cached) {
+            return Ok(response);
+        }
+        let response = self.request(&mut upstream, &request, arguments)?;
+        if cache_interval.is_some() {
+            self.cache_write()
+                .insert(request, (Instant::now(), response.clone()));
+        } else if request == "addpeer" || request == "removepeer" {
+            self.cache_write().clear();
+        }
+        Ok(response)
+    }
+
+    fn request(
+        &self,
+        upstream: &mut Option<Endpoint<S>>,
+        request: &str,
+        arguments: &HashMap<String, Value>,
+    ) -> Result<Value, String> {
+        let endpoint = match upstream {
+            Some(endpoint) => endpoint,
+            None => {
+                let socket =
+                    (self.connect)().map_err(|err| format!("Router is unavailable: {err}"))?;
+                upstream.insert(Endpoint::attach(socket))
+            }
+        };
+        match endpoint.request_raw(request, arguments.clone()) {
+            Ok(raw) if raw.is_success() => Ok(raw.response),
+            Ok(raw) => Err(raw.error.unwrap_or_else(|| "Unknown".to_string())),
+            Err(err) => {
+                *upstream = None;
+                Err(format!("Router is unavailable: {err}"))
+            }
+        }
+    }
+
+    fn cache_read(&self) -> RwLockReadGuard<'_, Cache> {
+        self.cache.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn cache_write(&self) -> RwLockWriteGuard<'_, Cache> {
+        self.cache.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn accept_failed(err: io::Error) -> io::Result<()> {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(target: "yggdrasilctl", error = %err, "Failed to accept a proxy client");
+    #[cfg(not(feature = "tracing"))]
+    let _ = err;
+    // Errors such as running out of file descriptors repeat until something changes
+    std::thread::sleep(Duration::from_millis(100));
+    Ok(())
+}
+
+/// User ID of the process on the other side of `socket`.
+#[cfg(target_os = "linux")]
+pub fn peer_uid(socket: &UnixStream) -> io::Result<u32> {
+    use std::os::fd::AsRawFd;
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and `len` holds the size of `cred`
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match ret {
+        0 => Ok(cred.uid),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// User ID of the process on the other side of `socket`.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn peer_uid(socket: &UnixStream) -> io::Result<u32> {
+    use std::os::fd::AsRawFd;
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: `uid` and `gid` are valid for writes
+    match unsafe { libc::getpeereid(socket.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok(uid),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::server;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn policy() {
+        let policy = Policy::parse(
+            "
+# comment
+uid 0 allow *
+deny getNodeInfo
+allow get* list # trailing comment
+",
+        )
+        .unwrap();
+        assert!(policy.is_allowed("addpeer", Some(0)));
+        assert!(!policy.is_allowed("addpeer", Some(1000)));
+        assert!(!policy.is_allowed("addpeer", None));
+        assert!(!policy.is_allowed("getnodeinfo", None));
+        assert!(policy.is_allowed("GetPeers", Some(1000)));
+        assert!(policy.is_allowed("list", None));
+        assert!(!policy.is_allowed("listen", None));
+        assert!(Policy::parse("allow").is_err());
+        assert!(Policy::parse("uid root allow *").is_err());
+        assert!(Policy::parse("permit *").is_err());
+    }
+
+    #[test]
+    fn proxy() {
+        let (router, upstream) = UnixStream::pair().unwrap();
+        let upstream = Mutex::new(Some(upstream));
+        let mut server = server::Server::new();
+        server.register(Peers(Default::default()));
+        // Serves until the proxy is dropped
+        std::thread::spawn(move || server.serve_connection(router).unwrap());
+        let mut proxy = Proxy::new(
+            move || Ok(upstream.lock().unwrap().take().unwrap()),
+            Policy::read_only(),
+        );
+        let denied = Arc::new(Mutex::new(Vec::new()));
+        proxy.on_denied({
+            let denied = denied.clone();
+            move |request, uid| denied.lock().unwrap().push((request.to_string(), uid))
+        });
+
+        let (client, socket) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&socket).ok(), peer_uid(&client).ok());
+        std::thread::scope(|scope| {
+            scope.spawn(|| proxy.serve_connection(socket, Some(1000)).unwrap());
+
+            let mut e = Endpoint::attach_version(client, crate::RouterVersion::v0_5_0__);
+            let err = e.add_peer("tcp://a:1".to_string(), None).unwrap();
+            assert_eq!(err, Err("Permission denied".to_string()));
+            let list = e.list().unwrap().unwrap();
+            let list: Vec<_> = list.iter().map(|e| e.command.as_str()).collect();
+            assert_eq!(list, ["getpeers", "list"]);
+            assert_eq!(e.get_peers().unwrap().unwrap(), []);
+        });
+        assert_eq!(
+            *denied.lock().unwrap(),
+            [("addpeer".to_string(), Some(1000))]
+        );
+    }
+
+    #[test]
+    fn cache() {
+        let (router, upstream) = UnixStream::pair().unwrap();
+        let upstream = Mutex::new(Some(upstream));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut server = server::Server::new();
+        server.register(Peers(calls.clone()));
+        server.register(AddPeer);
+        std::thread::spawn(move || server.serve_connection(router).unwrap());
+        let mut proxy = Proxy::new(
+            move || Ok(upstream.lock().unwrap().take().unwrap()),
+            Policy {
+                rules: vec![Rule::allow("*")],
+            },
+        );
+        proxy.set_cache_interval(Some(Duration::from_secs(3600)));
+
+        let (client, socket) = UnixStream::pair().unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| proxy.serve_connection(socket, None).unwrap());
+
+            let mut e = Endpoint::attach_version(client, crate::RouterVersion::v0_5_0__);
+            e.get_peers().unwrap().unwrap();
+            e.get_peers().unwrap().unwrap();
+            assert_eq!(calls.load(Ordering::Relaxed), 1);
+            e.add_peer("tcp://a:1".to_string(), None).unwrap().unwrap();
+            e.get_peers().unwrap().unwrap();
+            assert_eq!(calls.load(Ordering::Relaxed), 2);
+            proxy.refresh_cache();
+            assert_eq!(calls.load(Ordering::Relaxed), 3);
+            e.get_peers().unwrap().unwrap();
+            assert_eq!(calls.load(Ordering::Relaxed), 3);
+        });
+    }
+
+    struct Peers(Arc<AtomicUsize>);
+
+    impl server::Handler for Peers {
+        fn name(&self) -> &str {
+            "getpeers"
+        }
+        fn handle(&self, _: &HashMap<String, Value>) -> Result<Value, String> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(serde_json::json!({ "peers": [] }))
+        }
+    }
+
+    struct AddPeer;
+
+    impl server::Handler for AddPeer {
+        fn name(&self) -> &str {
+            "addpeer"
+        }
+        fn handle(&self, _: &HashMap<String, Value>) -> Result<Value, String> {
+            Ok(serde_json::json!({}))
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use super::*;
+
+use std::{hash::Hash, time::Instant};
+
+/// Estimates `rate_recvd` and `rate_sent` from traffic counters of successive
+/// `get_peers` and `get_sessions` results.
+///
+/// Routers before v0.5.10 don't report peer rates, and no router reports session rates.
+/// Rates already reported by the router are left untouched.
+#[derive(Debug, Default)]
+pub struct RateEstimator {
+    peers: HashMap<PeerKey, (Instant, Counters)>,
+    sessions: HashMap<String, (Instant, Counters)>,
+}
+
+impl RateEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update_peers(&mut self, peers: &mut [PeerEntry]) {
+        let now = Instant::now();
+        update(
+            &mut self.peers,
+            now,
+            peers.iter_mut().map(|e| {
+                let key = peer_key(e);
+                let counters = Counters::from(&*e);
+                (key, counters, &mut e.rate_recvd, &mut e.rate_sent)
+            }),
+        );
+    }
+
+    pub fn update_sessions(&mut self, sessions: &mut [SessionEntry]) {
+        let now = Instant::now();
+        update(
+            &mut self.sessions,
+            now,
+            sessions.iter_mut().map(|e| {
+                let key = e.key.clone();
+                let counters = Counters::from(&*e);
+                (key, counters, &mut e.rate_recvd, &mut e.rate_sent)
+            }),
+        );
+    }
+}
+
+fn update<'a, K: Eq + Hash>(
+    known: &mut HashMap<K, (Instant, Counters)>,
+    now: Instant,
+    entries: impl Iterator<Item = (K, Counters, &'a mut Option<u64>, &'a mut Option<u64>)>,
+) {
+    let mut seen = HashMap::with_capacity(known.len());
+    for (key, counters, rate_recvd, rate_sent) in entries {
+        if let Some((time, older)) = known.remove(&key) {
+            let delta = counters.delta(&older, Some(now.duration_since(time)));
+            if rate_recvd.is_none() {
+                *rate_recvd = delta.rate_recvd.map(|rate| rate.round() as u64);
+            }
+            if rate_sent.is_none() {
+                *rate_sent = delta.rate_sent.map(|rate| rate.round() as u64);
+            }
+        }
+        seen.insert(key, (now, counters));
+    }
+    // Forget entries that have gone away
+    *known = seen;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions() {
+        let session = |bytes_recvd, uptime| SessionEntry {
+            address: Ipv6Addr::LOCALHOST,
+            key: "a".to_string(),
+            bytes_recvd: Some(bytes_recvd),
+            bytes_sent: None,
+            uptime: Some(uptime),
+            rate_recvd: None,
+            rate_sent: None,
+            extra: Default::default(),
+        };
+        let mut estimator = RateEstimator::new();
+
+        let mut sessions = [session(100, 10.0)];
+        estimator.update_sessions(&mut sessions);
+        assert_eq!(sessions[0].rate_recvd, None);
+
+        let mut sessions = [session(600, 15.0)];
+        estimator.update_sessions(&mut sessions);
+        assert_eq!(sessions[0].rate_recvd, Some(100));
+        assert_eq!(sessions[0].rate_sent, None);
+
+        // Reconnected
+        let mut sessions = [session(40, 2.0)];
+        estimator.update_sessions(&mut sessions);
+        assert_eq!(sessions[0].rate_recvd, Some(20));
+    }
+
+    #[test]
+    fn disconnected_peers() {
+        // Disconnected peers of v0.5 routers have no key
+        let peer = |remote: &str, bytes_recvd, uptime| PeerEntry {
+            address: None,
+            key: String::new(),
+            port: 0,
+            priority: None,
+            remote: Some(remote.to_string()),
+            bytes_recvd: Some(bytes_recvd),
+            bytes_sent: None,
+            uptime: Some(uptime),
+            up: false,
+            inbound: false,
+            latency: None,
+            last_error: None,
+            last_error_time: None,
+            cost: None,
+            rate_recvd: None,
+            rate_sent: None,
+            extra: Default::default(),
+        };
+        let mut estimator = RateEstimator::new();
+
+        let mut peers = [peer("tls://a:1", 100, 10.0), peer("tls://b:1", 5000, 10.0)];
+        estimator.update_peers(&mut peers);
+
+        let mut peers = [peer("tls://a:1", 600, 15.0), peer("tls://b:1", 6000, 20.0)];
+        estimator.update_peers(&mut peers);
+        assert_eq!(peers[0].rate_recvd, Some(100));
+        assert_eq!(peers[1].rate_recvd, Some(100));
+    }
+}
@@ -0,0 +1,10 @@
+use super::*;
+
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedNodeInfo {
+    pub time: SystemTime,
+    /// Admin API error is cached too
+    pub result: Result<NodeInfo, String>,
+}
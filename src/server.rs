@@ -0,0 +1,155 @@
+//! Server side of the Admin API protocol, for services queryable with the same tools as the router.
+//!
+//! ```rust,ignore
+//! use yggdrasilctl::server::{Handler, Server};
+//!
+//! struct Uptime(std::time::Instant);
+//!
+//! impl Handler for Uptime {
+//!     fn name(&self) -> &str {
+//!         "getuptime"
+//!     }
+//!     fn handle(&self, _: &HashMap<String, Value>) -> Result<Value, String> {
+//!         Ok(json!({ "uptime": self.0.elapsed().as_secs_f64() }))
+//!     }
+//! }
+//!
+//! let mut server = Server::new();
+//! server.register(Uptime(std::time::Instant::now()));
+//! server.serve(UnixListener::bind("/run/companion.sock")?.incoming())?;
+//! ```
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::HashMap, io};
+
+// Server of the blocking API takes precedence, same as `Endpoint`
+#[cfg(feature = "use_std")]
+pub use crate::blocking::Server;
+
+#[cfg(not(feature = "use_std"))]
+#[cfg(any(feature = "use_tokio", feature = "use_futures"))]
+pub use crate::r#async::Server;
+
+/// Request handler, registered with `Server::register`.
+pub trait Handler: Send + Sync {
+    /// Request name, matched ignoring case
+    fn name(&self) -> &str;
+
+    /// Shown in the response to `list`
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// Argument names, shown in the response to `list`
+    fn fields(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Returns either `response` or `error` of the response envelope.
+    fn handle(&self, arguments: &HashMap<String, Value>) -> Result<Value, String>;
+}
+
+#[derive(Serialize)]
+struct Response {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    request: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<Value>,
+}
+
+pub(crate) fn respond(request: Value, result: Result<Value, String>) -> io::Result<Vec<u8>> {
+    let response = match result {
+        Ok(response) => Response {
+            status: "success",
+            error: None,
+            request,
+            response: Some(response),
+        },
+        Err(error) => Response {
+            status: "error",
+            error: Some(error),
+            request,
+            response: None,
+        },
+    };
+    // Formatted like responses of the router, which `Endpoint` relies on
+    let mut buf = serde_json::to_vec_pretty(&response)?;
+    buf.push(b'\n');
+    Ok(buf)
+}
+
+/// Largest request accepted by `Server` unless set otherwise, see `Server::set_max_request_size`.
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// Connections served at once by `Server::serve` unless set otherwise.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Finds where a request ends in the received bytes, since requests are not delimited.
+/// Bytes are scanned once, however they are split across reads.
+#[derive(Default)]
+pub(crate) struct RequestScanner {
+    // Next byte to scan
+    pos: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl RequestScanner {
+    /// Length of the first value in `buf`, once it's complete.
+    /// Anything but an object or array is reported as a value of its first byte,
+    /// which then fails to parse as a request.
+    pub fn next(&mut self, buf: &[u8]) -> Option<usize> {
+        while self.pos < buf.len() {
+            let byte = buf[self.pos];
+            self.pos += 1;
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match byte {
+                b'"' if self.depth > 0 => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' if self.depth > 0 => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Some(self.pos);
+                    }
+                }
+                _ if self.depth > 0 || byte.is_ascii_whitespace() => {}
+                _ => return Some(self.pos),
+            }
+        }
+        None
+    }
+
+    /// Continue with the bytes following the value returned by `next`.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanner() {
+        let mut scanner = RequestScanner::default();
+        let request = br#" {"request":"a}\"","arguments":{"b":[1]}}{"#;
+        assert_eq!(scanner.next(&request[..10]), None);
+        assert_eq!(scanner.next(&request[..20]), None);
+        assert_eq!(scanner.next(request), Some(request.len() - 1));
+        scanner.reset();
+        assert_eq!(scanner.next(b"\n x{}"), Some(3));
+    }
+}
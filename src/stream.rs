@@ -0,0 +1,251 @@
+//! Incremental scanner of responses, extracting elements of `.response.<field>` array as bytes arrive.
+
+use super::*;
+use std::io::{Error, ErrorKind};
+
+pub(crate) struct ArrayScanner<'f> {
+    field: &'f str,
+    buf: Vec<u8>,
+    // Next byte to scan
+    pos: usize,
+    total: usize,
+    // Open containers, either `{` or `[`
+    stack: Vec<u8>,
+    in_string: bool,
+    escaped: bool,
+    expect_key: bool,
+    string_is_key: bool,
+    string_start: usize,
+    // Last keys seen in the envelope and in `.response`
+    keys: [Option<String>; 2],
+    status: Option<String>,
+    error: Option<String>,
+    in_array: bool,
+    array_seen: bool,
+    element_start: usize,
+    done: bool,
+}
+
+impl<'f> ArrayScanner<'f> {
+    pub fn new(field: &'f str) -> Self {
+        Self {
+            field,
+            buf: Vec::new(),
+            pos: 0,
+            total: 0,
+            stack: Vec::new(),
+            in_string: false,
+            escaped: false,
+            expect_key: false,
+            string_is_key: false,
+            string_start: 0,
+            keys: [None, None],
+            status: None,
+            error: None,
+            in_array: false,
+            array_seen: false,
+            element_start: 0,
+            done: false,
+        }
+    }
+
+    /// Bytes fed so far.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Whether the envelope is closed and followed by a newline, which routers always send.
+    pub fn is_complete(&self) -> bool {
+        self.done && self.buf[self.pos..].contains(&b'\n')
+    }
+
+    /// Scan `bytes`, passing every complete element of the array to `on_element`.
+    /// Only the incomplete element is kept buffered.
+    pub fn feed(
+        &mut self,
+        bytes: &[u8],
+        on_element: &mut impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.total += bytes.len();
+        self.buf.extend_from_slice(bytes);
+        while self.pos < self.buf.len() && !self.done {
+            let pos = self.pos;
+            let byte = self.buf[pos];
+            self.pos += 1;
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                    self.end_string(pos)?;
+                }
+                continue;
+            }
+            match byte {
+                b'"' => {
+                    self.in_string = true;
+                    self.string_is_key = self.expect_key;
+                    self.string_start = pos;
+                }
+                b'{' | b'[' => {
+                    if byte == b'['
+                        && self.stack == b"{{"
+                        && self.keys[0].as_deref() == Some("response")
+                        && self.keys[1].as_deref() == Some(self.field)
+                    {
+                        self.in_array = true;
+                        self.array_seen = true;
+                        self.element_start = pos + 1;
+                    }
+                    self.stack.push(byte);
+                    self.expect_key = byte == b'{';
+                }
+                b'}' | b']' => {
+                    if self.in_array && self.stack.len() == 3 {
+                        self.element(pos, on_element)?;
+                        self.in_array = false;
+                    }
+                    self.stack.pop();
+                    self.done = self.stack.is_empty();
+                    self.expect_key = false;
+                }
+                b',' => {
+                    if self.in_array && self.stack.len() == 3 {
+                        self.element(pos, on_element)?;
+                        self.element_start = pos + 1;
+                    }
+                    self.expect_key = self.stack.last() == Some(&b'{');
+                }
+                b':' => self.expect_key = false,
+                _ => {}
+            }
+        }
+        self.compact();
+        Ok(())
+    }
+
+    fn end_string(&mut self, end: usize) -> io::Result<()> {
+        let depth = self.stack.len();
+        let wanted = match (depth, self.string_is_key) {
+            (1 | 2, true) => true,
+            (1, false) => matches!(self.keys[0].as_deref(), Some("status" | "error")),
+            _ => false,
+        };
+        if !wanted {
+            return Ok(());
+        }
+        let string: String = serde_json::from_slice(&self.buf[self.string_start..=end])
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        match (depth, self.string_is_key) {
+            (1, true) => self.keys = [Some(string), None],
+            (_, true) => self.keys[1] = Some(string),
+            _ if self.keys[0].as_deref() == Some("status") => self.status = Some(string),
+            _ => self.error = Some(string),
+        }
+        Ok(())
+    }
+
+    fn element(
+        &mut self,
+        end: usize,
+        on_element: &mut impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let element = self.buf[self.element_start..end].trim_ascii();
+        match element.is_empty() {
+            // Empty array
+            true => Ok(()),
+            false => on_element(element),
+        }
+    }
+
+    // Drop bytes that are scanned and not part of an incomplete element or string
+    fn compact(&mut self) {
+        let mut keep = self.pos;
+        if self.in_array {
+            keep = keep.min(self.element_start);
+        }
+        if self.in_string {
+            keep = keep.min(self.string_start);
+        }
+        self.buf.drain(..keep);
+        self.pos -= keep;
+        self.element_start = self.element_start.saturating_sub(keep);
+        self.string_start = self.string_start.saturating_sub(keep);
+    }
+
+    /// Result of the request, once the input is exhausted.
+    pub fn finish(self) -> RequestResult<()> {
+        if !self.done {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Response ended before it was complete",
+            ));
+        }
+        match self.status.as_deref() {
+            Some("success") if self.array_seen => Ok(Ok(())),
+            Some("success") => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Response has no {:?} array", self.field),
+            )),
+            _ => Ok(Err(self.error.unwrap_or_else(|| "Unknown".to_string()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(response: &[u8], chunk: usize) -> (Vec<Value>, RequestResult<()>) {
+        let mut elements = Vec::new();
+        let mut scanner = ArrayScanner::new("tree");
+        for bytes in response.chunks(chunk) {
+            scanner
+                .feed(bytes, &mut |element| {
+                    elements.push(serde_json::from_slice(element).unwrap());
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert!(scanner.is_complete());
+        (elements, scanner.finish())
+    }
+
+    #[test]
+    fn scanner() {
+        let response = serde_json::json!({
+            "status": "success",
+            "request": { "request": "gettree", "arguments": { "tree": ["]"] } },
+            "response": { "other": [1], "tree": [
+                { "key": "a\"]},", "nested": [{}, []] },
+                { "key": "b" },
+            ]},
+        });
+        for pretty in [false, true] {
+            let mut bytes = match pretty {
+                true => serde_json::to_vec_pretty(&response).unwrap(),
+                false => serde_json::to_vec(&response).unwrap(),
+            };
+            bytes.push(b'\n');
+            for chunk in [1, 7, bytes.len()] {
+                let (elements, result) = scan(&bytes, chunk);
+                assert_eq!(
+                    elements,
+                    response["response"]["tree"].as_array().unwrap()[..]
+                );
+                assert_eq!(result.unwrap(), Ok(()));
+            }
+        }
+
+        let (elements, result) = scan(b"{\"status\":\"error\",\"error\":\"a\\\"b\"}\n", 3);
+        assert!(elements.is_empty());
+        assert_eq!(result.unwrap(), Err("a\"b".to_string()));
+
+        let (_, result) = scan(b"{\"status\":\"success\",\"response\":{\"tree\":[]}}\n", 1);
+        assert_eq!(result.unwrap(), Ok(()));
+        let (_, result) = scan(b"{\"status\":\"success\",\"response\":{}}\n", 1);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}